@@ -0,0 +1,65 @@
+//! Scenes shared by the benchmarks. Paths are relative to the package root,
+//! where `cargo bench` runs.
+
+// Each benchmark only uses part of this module.
+#![allow(dead_code)]
+
+use glam::{vec2, Vec2};
+use ray_tracing::camera::Camera;
+use ray_tracing::ray::Ray;
+use ray_tracing::scene::Scene;
+use ray_tracing::scene_file::SceneFile;
+
+#[allow(unused, clippy::all)]
+#[path = "../../src/bin/scene1.rs"]
+mod scene1;
+#[allow(unused, clippy::all)]
+#[path = "../../src/bin/scene2.rs"]
+mod scene2;
+
+/// Resolution of the rendered frames and of the batches of camera rays.
+pub static RESOLUTION: Vec2 = vec2(320., 240.);
+
+pub struct BenchScene {
+    pub name: &'static str,
+    pub scene: Scene,
+    pub camera: Camera,
+}
+
+/// The two demo scenes, and scene2 described as an SDF tree in a scene file.
+pub fn scenes() -> Vec<BenchScene> {
+    let with_resolution = |mut camera: Camera| {
+        camera.resolution = RESOLUTION;
+        camera
+    };
+    let file = SceneFile::load("./scenes/scene2.scene").unwrap_or_else(|e| panic!("{}", e));
+
+    vec![
+        BenchScene {
+            name: "scene1",
+            scene: scene1::scene().unwrap_or_else(|e| panic!("{}", e)),
+            camera: with_resolution(scene1::camera()),
+        },
+        BenchScene {
+            name: "scene2",
+            scene: scene2::scene().unwrap_or_else(|e| panic!("{}", e)),
+            camera: with_resolution(scene2::camera()),
+        },
+        BenchScene {
+            name: "scene2_file",
+            scene: file.scene,
+            camera: with_resolution(file.camera.unwrap_or_else(scene2::camera)),
+        },
+    ]
+}
+
+/// One camera ray through the center of every pixel.
+pub fn camera_rays(scene: &Scene, camera: &Camera) -> Vec<Ray> {
+    let (w, h) = (camera.resolution.x as u32, camera.resolution.y as u32);
+    (0..w * h)
+        .map(|i| {
+            let coord = vec2((i % w) as f32 + 0.5, (i / w) as f32 + 0.5);
+            scene.camera_ray(camera, coord)
+        })
+        .collect()
+}
@@ -0,0 +1,43 @@
+//! Full frames of the demo scenes at a fixed resolution, reported as primary
+//! rays per second.
+//!
+//! Run with `cargo bench --bench frames`.
+
+mod common;
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ray_tracing::renderer::{RenderMode, Renderer};
+
+use common::scenes;
+
+fn frames(c: &mut Criterion) {
+    for s in scenes() {
+        let pixels = (s.camera.resolution.x * s.camera.resolution.y) as u64;
+        let mut group = c.benchmark_group(format!("frame/{}", s.name));
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(pixels));
+
+        group.bench_function("fast", |b| {
+            let mut renderer = Renderer::new();
+            b.iter(|| renderer.render_image(black_box(&s.scene), &s.camera, 1))
+        });
+        group.bench_function("fast_scalar", |b| {
+            let mut renderer = Renderer::new();
+            renderer.packets = false;
+            b.iter(|| renderer.render_image(black_box(&s.scene), &s.camera, 1))
+        });
+        // One path traced sample per pixel, without the denoiser.
+        group.bench_function("path_traced", |b| {
+            let mut renderer = Renderer::new();
+            renderer.mode = RenderMode::PathTraced;
+            renderer.denoise = false;
+            b.iter(|| renderer.render_image(black_box(&s.scene), &s.camera, 1))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
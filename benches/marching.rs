@@ -0,0 +1,124 @@
+//! Hot paths of the ray marcher, reported as elements per second: SDF
+//! evaluations for the primitives, rays for everything else.
+//!
+//! Run with `cargo bench --bench marching`; criterion compares each run
+//! against the previous one stored under `target/criterion`.
+
+mod common;
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use glam::{vec3, Vec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ray_tracing::ray::Ray;
+use ray_tracing::ray_marching::sdfs::{
+    box_sdf, cylinder_sdf, line_sdf, plane_sdf, sphere_sdf, torus_sdf,
+};
+use ray_tracing::ray_marching::RayMarching;
+
+use common::{camera_rays, scenes};
+
+static POINTS: usize = 4096;
+/// Sharpness of the soft shadows, as used by the renderer.
+static SHADOW_K: f32 = 32.;
+
+fn primitives(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(1);
+    let points: Vec<Vec3> = (0..POINTS)
+        .map(|_| vec3(rng.gen(), rng.gen(), rng.gen()) * 4. - 2.)
+        .collect();
+
+    let mut group = c.benchmark_group("sdf");
+    group.throughput(Throughput::Elements(POINTS as u64));
+    let mut bench = |name: &str, f: &dyn Fn(Vec3) -> f32| {
+        group.bench_function(name, |b| {
+            b.iter(|| points.iter().map(|&p| f(black_box(p))).sum::<f32>())
+        });
+    };
+    bench("sphere", &|p| sphere_sdf(p, 1.));
+    bench("box", &|p| box_sdf(p, vec3(0.5, 1., 0.5), 0.1));
+    bench("plane", &|p| plane_sdf(p, Vec3::ZERO, Vec3::Y));
+    bench("cylinder", &|p| cylinder_sdf(p, 0.5, 0.05, 2.));
+    bench("capsule", &|p| line_sdf(p, Vec3::ZERO, Vec3::Y, 0.3));
+    bench("torus", &|p| torus_sdf(p, 1., 0.25));
+    group.finish();
+}
+
+fn marching(c: &mut Criterion) {
+    for s in scenes() {
+        let rm = RayMarching { scene: &s.scene };
+        let rays = camera_rays(&s.scene, &s.camera);
+        // Primary hits, shared by the benchmarks shading them.
+        let hits: Vec<(Vec3, Vec3)> = rays
+            .iter()
+            .filter_map(|ray| {
+                let hit = rm.march_ray(ray)?;
+                let p = ray.origin + ray.direction * hit.dist;
+                Some((p, hit.normal.unwrap_or_else(|| rm.normal(p))))
+            })
+            .collect();
+        let sun = s.scene.sun_direction();
+
+        let mut group = c.benchmark_group(format!("marching/{}", s.name));
+        group.sample_size(20);
+
+        group.throughput(Throughput::Elements(rays.len() as u64));
+        group.bench_function(BenchmarkId::new("march_ray", rays.len()), |b| {
+            b.iter(|| {
+                rays.iter()
+                    .filter(|ray| rm.march_ray(black_box(ray)).is_some())
+                    .count()
+            })
+        });
+
+        // Shading of the primary rays, as in the fast render mode.
+        group.bench_function(BenchmarkId::new("path_trace", rays.len()), |b| {
+            let light = &s.scene.lights[0];
+            b.iter(|| {
+                rays.iter()
+                    .map(|ray| {
+                        let sky = s.scene.sky_color(ray.direction);
+                        let background = s.scene.background(ray.direction);
+                        s.scene
+                            .path_trace(black_box(ray), light, background, sky, 0)
+                    })
+                    .sum::<Vec3>()
+            })
+        });
+
+        group.throughput(Throughput::Elements(hits.len() as u64));
+        group.bench_function(BenchmarkId::new("normal", hits.len()), |b| {
+            b.iter(|| {
+                hits.iter()
+                    .map(|&(p, _)| rm.normal(black_box(p)))
+                    .sum::<Vec3>()
+            })
+        });
+        group.bench_function(BenchmarkId::new("shadow", hits.len()), |b| {
+            b.iter(|| {
+                hits.iter()
+                    .map(|&(p, n)| {
+                        let ray = Ray {
+                            origin: p + n * 0.0001,
+                            direction: sun,
+                        };
+                        rm.shadow(black_box(&ray), SHADOW_K)
+                    })
+                    .sum::<f32>()
+            })
+        });
+        group.bench_function(BenchmarkId::new("occlusion", hits.len()), |b| {
+            b.iter(|| {
+                hits.iter()
+                    .map(|&(p, n)| rm.occlusion(black_box(p), n))
+                    .sum::<f32>()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, primitives, marching);
+criterion_main!(benches);
@@ -1,23 +1,55 @@
-use glam::{vec4, Vec2, Vec4};
+use glam::{uvec2, vec3, vec4, Vec2, Vec4};
 use rand::rngs::ThreadRng;
 use sdl2::event::{Event, WindowEvent};
 
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::PixelFormatEnum;
 use std::time::Instant;
 
-use crate::camera::{Camera, CameraEvent};
+use crate::camera::{Camera, CameraEvent, Controller};
+use crate::camera_path::{CameraKeyframe, CameraPath};
+use crate::capture::{Capture, Recorder};
 
-use crate::renderer::Renderer;
+use crate::overlay::Overlay;
+use crate::renderer::{RenderMode, Renderer};
+use crate::resolution::ResolutionController;
 use crate::scene::Scene;
+use crate::scene_file::{SceneFile, SceneWatcher};
 use crate::utils::errors::AppError;
 
+/// Seconds between keyframes added from the app.
+static KEYFRAME_SPACING: f32 = 2.;
+static CAMERA_PATH_FILE: &str = "./camera.path";
+static SCRUB_STEP: f32 = 0.25;
+
 pub struct App3D {}
 
 impl App3D {
     pub fn run(
         camera: &mut Camera,
         scene: &mut Scene,
+    ) -> Result<(), AppError> {
+        Self::run_with(camera, scene, None)
+    }
+
+    /// Runs the scene described in `path`, reloading it whenever the file or
+    /// its textures change on disk. The camera is kept across reloads.
+    pub fn run_file(path: &str) -> Result<(), AppError> {
+        let file = SceneFile::load(path)?;
+        let watcher = SceneWatcher::new(path, &file.dependencies);
+        let mut scene = file.scene;
+        let mut camera = file
+            .camera
+            .unwrap_or_else(|| Camera::new_with_pos(vec3(0., 1., 11.), vec3(0., 0., -1.)));
+
+        Self::run_with(&mut camera, &mut scene, Some(watcher))
+    }
+
+    fn run_with(
+        camera: &mut Camera,
+        scene: &mut Scene,
+        mut watcher: Option<SceneWatcher>,
     ) -> Result<(), AppError> {
         let sdl_context = sdl2::init()?;
 
@@ -39,23 +71,39 @@ impl App3D {
         let texture_creator = canvas.texture_creator();
 
         let size = canvas.output_size().unwrap();
+        let mut window_size = uvec2(size.0, size.1);
+        let mut render_size = window_size;
 
         let mut texture = texture_creator
             .create_texture_streaming(PixelFormatEnum::ABGR8888, size.0, size.1)
             .map_err(|e| e.to_string())?;
 
-        let renderer = Renderer{};
+        let mut renderer = Renderer::new();
+        let mut resolution = ResolutionController::default();
+        let mut overlay = Overlay::new(&texture_creator, "./resources/OpenSans-Regular.ttf")?;
+        let capture = Capture::default();
+        let mut recorder = Recorder::default();
+        // Set once a recorded frame is saved, to step the scene to the next one.
+        let mut advance = false;
+        let mut camera_path = CameraPath::default();
+        // Playback time along the camera path, while it plays.
+        let mut playback: Option<f32> = None;
         let mut event_pump = sdl_context.event_pump()?;
         let mut changed: Option<(usize, usize)> = None;
+        // Error of the last scene reload, shown until a reload succeeds.
+        let mut load_error: Option<String> = None;
 
         let mut frame_time = Instant::now();
         let mut timer = Instant::now();
-        let start = Instant::now();
+        // Scene clock driving the timeline, paused and scrubbed from the keyboard.
+        let mut scene_time = 0f32;
+        let mut playing = true;
 
         let nanos = 1000000000. / 60.;
         let mut delta: f64 = 0.;
         let mut ups = 0u32;
         let mut fps = 0u32;
+        let mut last_fps = 0u32;
 
         camera.update(
             &vec![CameraEvent::Resize {
@@ -75,13 +123,20 @@ impl App3D {
         let mut down = false;
         let mut left = false;
         let mut right = false;
-        let num_cores = 200; //num_cpus::get();
+        let mut ascend = false;
+        let mut descend = false;
+        let mut roll_left = false;
+        let mut roll_right = false;
+        let mut fast = false;
+        let mut slow = false;
+        let mut mouse_button = MouseButton::Left;
 
         'running: loop {
             let elapsed = frame_time.elapsed();
             let elapsed_nanos = elapsed.as_nanos() as f64;
             let ts = elapsed.as_secs_f32();
             let mut rotateXY: Option<Vec2> = None;
+            let mut pan: Option<Vec2> = None;
 
             for event in event_pump.poll_iter() {
                 match event {
@@ -104,6 +159,88 @@ impl App3D {
                             Keycode::S => down = true,
                             Keycode::A => left = true,
                             Keycode::D => right = true,
+                            Keycode::E => ascend = true,
+                            Keycode::Q => descend = true,
+                            Keycode::Z => roll_left = true,
+                            Keycode::X => roll_right = true,
+                            Keycode::LShift | Keycode::RShift => fast = true,
+                            Keycode::LCtrl | Keycode::RCtrl => slow = true,
+                            Keycode::C => {
+                                let controller = match camera.controller {
+                                    Controller::Fly => Controller::Fps,
+                                    Controller::Fps => Controller::Orbit,
+                                    Controller::Orbit => Controller::Fly,
+                                };
+                                camera.set_controller(controller);
+                                updated = true;
+                            }
+                            Keycode::M => {
+                                renderer.mode = match renderer.mode {
+                                    RenderMode::Fast => RenderMode::PathTraced,
+                                    RenderMode::PathTraced => RenderMode::Fast,
+                                };
+                                updated = true;
+                            }
+                            Keycode::T => renderer.temporal = !renderer.temporal,
+                            Keycode::N => renderer.denoise = !renderer.denoise,
+                            Keycode::R => resolution.enabled = !resolution.enabled,
+                            Keycode::F1 => overlay.visible = !overlay.visible,
+                            Keycode::F12 => {
+                                match capture.screenshot(&img, scene, camera, &renderer, scene_time)
+                                {
+                                    Ok(path) => println!("Saved {}", path),
+                                    Err(e) => println!("Screenshot failed: {:?}", e),
+                                }
+                            }
+                            Keycode::F10 => {
+                                if recorder.is_recording() {
+                                    recorder.stop();
+                                } else {
+                                    recorder.start()?;
+                                    advance = true;
+                                }
+                            }
+                            Keycode::I => {
+                                resolution.refine_when_idle = !resolution.refine_when_idle
+                            }
+                            Keycode::K => {
+                                let time = if camera_path.keyframes.is_empty() {
+                                    0.
+                                } else {
+                                    camera_path.duration() + KEYFRAME_SPACING
+                                };
+                                camera_path.add(CameraKeyframe::from_camera(camera, time));
+                            }
+                            Keycode::Backspace => {
+                                camera_path.keyframes.clear();
+                                playback = None;
+                            }
+                            Keycode::P => {
+                                playback = match playback {
+                                    Some(_) => None,
+                                    None => Some(0.),
+                                };
+                                updated |= playback.is_some() && camera_path.apply(camera, 0.);
+                            }
+                            Keycode::Space => playing = !playing,
+                            Keycode::Left | Keycode::Right | Keycode::Home => {
+                                scene_time = match code {
+                                    Keycode::Left => (scene_time - SCRUB_STEP).max(0.),
+                                    Keycode::Right => scene_time + SCRUB_STEP,
+                                    _ => 0.,
+                                };
+                                scene.animate(scene_time);
+                                updated = true;
+                            }
+                            Keycode::F5 => {
+                                if let Err(e) = camera_path.save(CAMERA_PATH_FILE) {
+                                    println!("Saving camera path failed: {:?}", e);
+                                }
+                            }
+                            Keycode::F6 => match CameraPath::load(CAMERA_PATH_FILE) {
+                                Ok(path) => camera_path = path,
+                                Err(e) => println!("Loading camera path failed: {:?}", e),
+                            },
                             _ => {}
                         };
                     }
@@ -121,6 +258,12 @@ impl App3D {
                             Keycode::S => down = false,
                             Keycode::A => left = false,
                             Keycode::D => right = false,
+                            Keycode::E => ascend = false,
+                            Keycode::Q => descend = false,
+                            Keycode::Z => roll_left = false,
+                            Keycode::X => roll_right = false,
+                            Keycode::LShift | Keycode::RShift => fast = false,
+                            Keycode::LCtrl | Keycode::RCtrl => slow = false,
                             _ => {}
                         };
                     }
@@ -129,12 +272,13 @@ impl App3D {
                         timestamp: _,
                         window_id: _,
                         which: _,
-                        mouse_btn: _,
+                        mouse_btn,
                         clicks: _,
                         x,
                         y,
                     } => {
                         mouse_pressed = true;
+                        mouse_button = mouse_btn;
                         last_mouse_pos = Vec2::new(x as f32, y as f32);
                         sdl_context.mouse().show_cursor(false);
                     }
@@ -157,15 +301,23 @@ impl App3D {
                         if mouse_pressed {
                             let mouse_pos = Vec2::new(x as f32, y as f32);
 
-                            let delta = (mouse_pos - last_mouse_pos) * 0.05;
+                            let moved = mouse_pos - last_mouse_pos;
+                            let delta = moved * 0.05;
 
                             last_mouse_pos = mouse_pos;
 
                             if delta.x != 0.0 || delta.y != 0.0 {
-                                rotateXY = Some(delta);
+                                match mouse_button {
+                                    MouseButton::Left => rotateXY = Some(delta),
+                                    _ => pan = Some(moved),
+                                }
                             }
                         }
                     }
+                    Event::MouseWheel { y, .. } => {
+                        camera.update(&vec![CameraEvent::Zoom { delta: y as f32 }], ts);
+                        updated = true;
+                    }
                     Event::Window {
                         timestamp: _,
                         window_id: _,
@@ -173,11 +325,9 @@ impl App3D {
                     } => match win_event {
                         WindowEvent::SizeChanged(w, h) => {
                             changed = Some((w as usize, h as usize));
-                            img = vec![0; (w * h * 4) as usize];
                         }
                         WindowEvent::Resized(w, h) => {
                             changed = Some((w as usize, h as usize));
-                            img = vec![0; (w * h * 4) as usize];
                         }
                         WindowEvent::Exposed => {}
                         _ => {}
@@ -188,61 +338,176 @@ impl App3D {
 
             frame_time = Instant::now();
 
-            delta += elapsed_nanos / nanos;
-            while delta >= 1. {
-                let mut events: Vec<CameraEvent> = vec![];
-                if up {
-                    events.push(CameraEvent::Up)
-                }
-                if down {
-                    events.push(CameraEvent::Down)
-                }
-                if left {
-                    events.push(CameraEvent::Left)
+            let mut events: Vec<CameraEvent> = vec![];
+            if up {
+                events.push(CameraEvent::Up)
+            }
+            if down {
+                events.push(CameraEvent::Down)
+            }
+            if left {
+                events.push(CameraEvent::Left)
+            }
+            if right {
+                events.push(CameraEvent::Right)
+            }
+            if ascend {
+                events.push(CameraEvent::Ascend)
+            }
+            if descend {
+                events.push(CameraEvent::Descend)
+            }
+            if roll_left {
+                events.push(CameraEvent::RollLeft)
+            }
+            if roll_right {
+                events.push(CameraEvent::RollRight)
+            }
+            if let Some(delta) = rotateXY {
+                events.push(CameraEvent::RotateXY { delta })
+            }
+            if let Some(delta) = pan {
+                events.push(CameraEvent::Pan { delta })
+            }
+            // Speed modifiers scale the time step of movements.
+            let boost = match (fast, slow) {
+                (true, false) => 4.,
+                (false, true) => 0.25,
+                _ => 1.,
+            };
+
+            if recorder.is_recording() {
+                // Simulated time only moves once the previous frame is saved.
+                delta = 0.;
+                if advance {
+                    if !events.is_empty() {
+                        camera.update(&events, recorder.timestep * boost);
+                    }
+                    scene_time = recorder.time();
+                    scene.animate(scene_time);
+                    updated = true;
+                    advance = false;
+
+                    // While recording the path follows the recorder's clock.
+                    if let Some(t) = playback.as_mut() {
+                        *t = recorder.time();
+                        updated |= camera_path.apply(camera, *t);
+                    }
                 }
-                if right {
-                    events.push(CameraEvent::Right)
+            } else {
+                delta += elapsed_nanos / nanos;
+
+                if let Some(t) = playback.as_mut() {
+                    *t += ts;
+                    updated |= camera_path.apply(camera, *t);
                 }
-                if let Some(delta) = rotateXY {
-                    events.push(CameraEvent::RotateXY { delta })
+
+                // The timeline follows wall time, like the camera path.
+                if playing {
+                    scene_time += ts;
+                    updated |= scene.animate(scene_time);
                 }
+            }
+
+            if playback.is_some_and(|t| !camera_path.looping && t > camera_path.duration()) {
+                playback = None;
+            }
 
+            while delta >= 1. {
                 if !events.is_empty() {
-                    camera.update(&events, ts);
+                    camera.update(&events, ts * boost);
                     updated = true;
                 }
 
                 // App state updates here.
 
-                let u: bool = (scene.update)(scene, start.elapsed().as_secs_f32());
-                if !updated {
-                    updated = u;
-                }
-
                 ups += 1;
                 delta -= 1.;
             }
 
+            if let Some(watcher) = watcher.as_mut() {
+                if watcher.poll() {
+                    match SceneFile::load(&watcher.path) {
+                        Ok(file) => {
+                            watcher.watch(&file.dependencies);
+                            *scene = file.scene;
+                            scene.animate(scene_time);
+                            load_error = None;
+                            updated = true;
+                            println!("Reloaded {}", watcher.path);
+                        }
+                        Err(e) => {
+                            println!("Reloading {} failed: {:?}", watcher.path, e);
+                            load_error = Some(format!("{:?}", e));
+                        }
+                    }
+                }
+            }
+
             if let Some((w, h)) = changed {
                 updated = true;
+                window_size = uvec2(w as u32, h as u32);
+                changed = None;
+            }
+
+            resolution.update(updated, ts);
+
+            // Render at the controller's resolution and let the canvas upscale it.
+            let size = if recorder.is_recording() {
+                window_size
+            } else {
+                resolution.resolution(window_size)
+            };
+            if size != render_size {
+                updated = true;
+                render_size = size;
                 camera.update(
                     &vec![CameraEvent::Resize {
-                        w: w as usize,
-                        h: h as usize,
+                        w: size.x as usize,
+                        h: size.y as usize,
                     }],
                     ts,
                 );
 
+                img = vec![0; (size.x * size.y * 4) as usize];
                 texture = texture_creator
-                    .create_texture_streaming(PixelFormatEnum::ABGR8888, w as u32, h as u32)
+                    .create_texture_streaming(PixelFormatEnum::ABGR8888, size.x, size.y)
                     .map_err(|e| e.to_string())?;
-
-                changed = None;
             }
 
             canvas.clear();
-            renderer.render(scene, &mut texture, &mut img, &camera, updated, num_cores)?;
+            if let Some(frame_time) =
+                renderer.render(scene, &mut texture, &mut img, &camera, updated)?
+            {
+                resolution.frame_rendered(frame_time.as_secs_f32());
+
+                let converged = renderer.mode == RenderMode::Fast
+                    || renderer.samples() >= recorder.samples;
+                if recorder.is_recording() && converged {
+                    recorder.save_frame(&img, camera)?;
+                    advance = true;
+                }
+            }
             canvas.copy(&texture, None, None)?;
+            if let Some(error) = &load_error {
+                overlay.draw_error(&mut canvas, error)?;
+            } else if overlay.visible {
+                let mut lines = Overlay::lines(&renderer, camera);
+                lines.push(format!(
+                    "fps {}, resolution {}x{} ({:.2})",
+                    last_fps,
+                    render_size.x,
+                    render_size.y,
+                    resolution.scale()
+                ));
+                lines.push(format!(
+                    "time {:.2} / {:.2} s{}",
+                    scene_time,
+                    scene.timeline.duration(),
+                    if playing { "" } else { ", paused" }
+                ));
+                overlay.draw(&mut canvas, &lines)?;
+            }
             canvas.present();
 
             updated = false;
@@ -254,9 +519,18 @@ impl App3D {
                 timer = Instant::now();
                 canvas
                     .window_mut()
-                    .set_title(format!("ups {} / fps {}", ups, fps).as_str())
+                    .set_title(
+                        format!(
+                            "ups {} / fps {} / scale {:.2}",
+                            ups,
+                            fps,
+                            resolution.scale()
+                        )
+                        .as_str(),
+                    )
                     .map_err(|e| e.to_string())?;
                 ups = 0;
+                last_fps = fps;
                 fps = 0;
             }
         }
@@ -0,0 +1,66 @@
+use std::time::Instant;
+
+use glam::vec3;
+use ray_tracing::mesh::bvh::Aabb;
+use ray_tracing::mesh::polygonize::{Contouring, Polygonizer};
+use ray_tracing::scene_file::SceneFile;
+use ray_tracing::utils::errors::AppError;
+
+static USAGE: &str = "usage: export <scene file> <output.obj|ply|stl> \
+[--bounds x0 y0 z0 x1 y1 z1] [--resolution n] [--dual]";
+
+fn parse<T: std::str::FromStr>(value: Option<String>, flag: &str) -> Result<T, AppError> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| AppError::ErrorString(format!("invalid value for {}\n{}", flag, USAGE)))
+}
+
+pub fn main() -> Result<(), AppError> {
+    let mut args = std::env::args().skip(1);
+    let (Some(scene_path), Some(output)) = (args.next(), args.next()) else {
+        return Err(AppError::ErrorString(USAGE.to_string()));
+    };
+
+    let mut polygonizer = Polygonizer::new(
+        Aabb {
+            min: vec3(-5., -5., -5.),
+            max: vec3(5., 5., 5.),
+        },
+        128,
+    );
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--bounds" => {
+                let mut v = [0f32; 6];
+                for x in v.iter_mut() {
+                    *x = parse(args.next(), &flag)?;
+                }
+                polygonizer.bounds = Aabb {
+                    min: vec3(v[0], v[1], v[2]),
+                    max: vec3(v[3], v[4], v[5]),
+                };
+            }
+            "--resolution" => polygonizer.resolution = parse(args.next(), &flag)?,
+            "--dual" => polygonizer.method = Contouring::DualContouring,
+            _ => {
+                return Err(AppError::ErrorString(format!(
+                    "unknown argument {}\n{}",
+                    flag, USAGE
+                )))
+            }
+        }
+    }
+
+    let file = SceneFile::load(&scene_path)?;
+    let start = Instant::now();
+    let mesh = polygonizer.polygonize(&file.scene);
+    mesh.save(&output, &file.scene)?;
+    println!(
+        "Wrote {} ({} vertices, {} triangles) in {:.2?}",
+        output,
+        mesh.positions.len(),
+        mesh.faces.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
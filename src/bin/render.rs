@@ -0,0 +1,236 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use glam::{vec2, vec3};
+use ray_tracing::camera::Camera;
+use ray_tracing::camera_path::CameraPath;
+use ray_tracing::capture::{AovPass, Capture};
+use ray_tracing::renderer::{RenderMode, Renderer};
+use ray_tracing::scene_file::SceneFile;
+use ray_tracing::utils::errors::AppError;
+use ray_tracing::utils::image::ImageUtils;
+
+static USAGE: &str = "usage: render <scene file> <output.png> \
+[--resolution w h] [--spp n] [--mode fast|path] [--no-denoise] \
+[--camera px py pz fx fy fz] [--camera-path file] [--frames first last] [--fps n] \
+[--aov depth,normal]";
+
+/// Options read from the command line.
+struct Options {
+    scene: String,
+    output: String,
+    width: u32,
+    height: u32,
+    samples: u32,
+    mode: RenderMode,
+    denoise: bool,
+    camera: Option<Camera>,
+    /// Keyframed camera played back over the frames.
+    camera_path: Option<CameraPath>,
+    /// Defaults to the whole camera path, or a single frame without one.
+    frames: Option<(u32, u32)>,
+    fps: f32,
+    aovs: Vec<AovPass>,
+}
+
+fn parse<T: std::str::FromStr>(value: Option<String>, flag: &str) -> Result<T, AppError> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| AppError::ErrorString(format!("invalid value for {}\n{}", flag, USAGE)))
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, AppError> {
+        let (Some(scene), Some(output)) = (args.next(), args.next()) else {
+            return Err(AppError::ErrorString(USAGE.to_string()));
+        };
+        let mut options = Options {
+            scene,
+            output,
+            width: 800,
+            height: 600,
+            samples: 64,
+            mode: RenderMode::Fast,
+            denoise: true,
+            camera: None,
+            camera_path: None,
+            frames: None,
+            fps: 30.,
+            aovs: vec![],
+        };
+
+        while let Some(flag) = args.next() {
+            match flag.as_str() {
+                "--resolution" => {
+                    options.width = parse(args.next(), &flag)?;
+                    options.height = parse(args.next(), &flag)?;
+                }
+                "--spp" => options.samples = parse(args.next(), &flag)?,
+                "--mode" => {
+                    options.mode = match args.next().as_deref() {
+                        Some("fast") => RenderMode::Fast,
+                        Some("path") => RenderMode::PathTraced,
+                        _ => {
+                            return Err(AppError::ErrorString(format!(
+                                "invalid value for {}\n{}",
+                                flag, USAGE
+                            )))
+                        }
+                    }
+                }
+                "--no-denoise" => options.denoise = false,
+                "--camera" => {
+                    let mut v = [0f32; 6];
+                    for x in v.iter_mut() {
+                        *x = parse(args.next(), &flag)?;
+                    }
+                    options.camera = Some(Camera::new_with_pos(
+                        vec3(v[0], v[1], v[2]),
+                        vec3(v[3], v[4], v[5]),
+                    ));
+                }
+                "--camera-path" => {
+                    let file: String = parse(args.next(), &flag)?;
+                    options.camera_path = Some(CameraPath::load(&file)?);
+                }
+                "--frames" => {
+                    options.frames = Some((parse(args.next(), &flag)?, parse(args.next(), &flag)?));
+                }
+                "--fps" => options.fps = parse(args.next(), &flag)?,
+                "--aov" => {
+                    let names: String = parse(args.next(), &flag)?;
+                    for name in names.split(',') {
+                        options.aovs.push(match name {
+                            "depth" => AovPass::Depth,
+                            "normal" => AovPass::Normal,
+                            _ => {
+                                return Err(AppError::ErrorString(format!(
+                                    "unknown AOV {}\n{}",
+                                    name, USAGE
+                                )))
+                            }
+                        });
+                    }
+                }
+                _ => {
+                    return Err(AppError::ErrorString(format!(
+                        "unknown argument {}\n{}",
+                        flag, USAGE
+                    )))
+                }
+            }
+        }
+
+        if options.width == 0 || options.height == 0 {
+            return Err(AppError::ErrorString(
+                "resolution must not be zero".to_string(),
+            ));
+        }
+        let (first, last) = options.frames();
+        if last < first || options.fps <= 0. {
+            return Err(AppError::ErrorString(format!(
+                "invalid frame range {}..{} at {} fps",
+                first, last, options.fps
+            )));
+        }
+        Ok(options)
+    }
+
+    fn frames(&self) -> (u32, u32) {
+        match (self.frames, &self.camera_path) {
+            (Some(frames), _) => frames,
+            (None, Some(path)) => (0, (path.duration() * self.fps).ceil() as u32),
+            (None, None) => (0, 0),
+        }
+    }
+
+    /// Output path without its extension. Frames are numbered when a range is
+    /// rendered.
+    fn base(&self, frame: u32) -> String {
+        let path = Path::new(&self.output);
+        let stem = path.with_extension("");
+        let stem = stem.to_string_lossy();
+        let (first, last) = self.frames();
+        if first == last {
+            stem.to_string()
+        } else {
+            format!("{}_{:05}", stem, frame)
+        }
+    }
+}
+
+fn run() -> Result<(), AppError> {
+    let options = Options::parse(std::env::args().skip(1))?;
+
+    let start = Instant::now();
+    let file = SceneFile::load(&options.scene)?;
+    let mut scene = file.scene;
+    let mut camera = options
+        .camera
+        .clone()
+        .or(file.camera)
+        .unwrap_or_else(|| Camera::new_with_pos(vec3(0., 1., 11.), vec3(0., 0., -1.)));
+    camera.resolution = vec2(options.width as f32, options.height as f32);
+    println!("Loaded {} in {:.2?}", options.scene, start.elapsed());
+
+    let mut renderer = Renderer::new();
+    renderer.mode = options.mode;
+    renderer.denoise = options.denoise;
+
+    let (first, last) = options.frames();
+    let total = Instant::now();
+    for frame in first..=last {
+        let frame_start = Instant::now();
+        let time = frame as f32 / options.fps;
+        if let Some(path) = &options.camera_path {
+            path.apply(&mut camera, time);
+        }
+        scene.animate(time);
+
+        let img = renderer.render_image_with_progress(&scene, &camera, options.samples, |spp| {
+            if options.mode == RenderMode::PathTraced {
+                print!("\rframe {} sample {}/{}", frame, spp, options.samples);
+                let _ = std::io::stdout().flush();
+            }
+        });
+        if options.mode == RenderMode::PathTraced {
+            println!();
+        }
+
+        let base = options.base(frame);
+        let path = format!("{}.png", base);
+        ImageUtils::save_rgba(&path, options.width, options.height, &img)?;
+        Capture::save_aovs(&base, &scene, &camera, &options.aovs)?;
+
+        let stats = renderer.stats();
+        println!(
+            "Wrote {} (frame {}/{}, t = {:.3}s) in {:.2?}, {:.2} Mrays/s",
+            path,
+            frame - first + 1,
+            last - first + 1,
+            time,
+            frame_start.elapsed(),
+            stats.rays_per_second / 1e6
+        );
+    }
+    if last > first {
+        println!(
+            "Rendered {} frames in {:.2?}",
+            last - first + 1,
+            total.elapsed()
+        );
+    }
+    Ok(())
+}
+
+pub fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
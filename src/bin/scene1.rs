@@ -4,21 +4,51 @@ use ray_tracing::camera::Camera;
 use ray_tracing::light::{Directional, Light, LightSource};
 use ray_tracing::ray::Ray;
 use ray_tracing::ray_marching::sdfs::{box_sdf, cylinder_sdf, line_sdf, plane_sdf, sphere_sdf};
+use ray_tracing::ray_marching::terrain::{HeightSource, Terrain};
 use ray_tracing::renderer::Renderer;
 use ray_tracing::scene::{Hit, Scene};
-use ray_tracing::utils::materials::{Material, MaterialType};
+use ray_tracing::timeline::{Animation, Easing, Timeline, Track};
+use ray_tracing::utils::materials::{Material, MaterialType, NormalMap};
 use ray_tracing::utils::math;
+use ray_tracing::utils::noise::{Basis, Fbm};
 use ray_tracing::utils::{errors::AppError, image::ImageUtils};
 
-fn update(scene: &mut Scene, time: f32) -> bool {
-    let l = &mut scene.lights[0];
-    if let Light::Directional(d) = l {
-        d.direction.z = (time * 0.4).sin();
-        //d.direction.x = (time*0.4).cos()*0.94;
-        // d.direction.x = 1. * (time*0.5).cos();
-        d.direction = d.direction.normalize();
+static TERRAIN: Terrain = Terrain {
+    source: HeightSource::Fbm(Fbm {
+        seed: 7,
+        basis: Basis::Perlin,
+        octaves: 6,
+        frequency: 0.25,
+        lacunarity: 2.0,
+        gain: 0.5,
+    }),
+    height: 0.6,
+    offset: -0.1,
+    lipschitz: 1.0,
+    lod_distance: 8.,
+    min_octaves: 2,
+};
+
+fn update(_scene: &mut Scene, _time: f32) -> bool {
+    false
+}
+
+/// Sun swinging back and forth over the pillars.
+fn timeline() -> Timeline {
+    let period = std::f32::consts::TAU / 0.4;
+    let sun = |z: f32| vec3(-0.2, -0.1, z);
+    Timeline {
+        animations: vec![Animation::LightDirection {
+            light: 0,
+            track: Track::new()
+                .key(0., sun(0.), Easing::EaseOut)
+                .key(period * 0.25, sun(1.), Easing::EaseIn)
+                .key(period * 0.5, sun(0.), Easing::EaseOut)
+                .key(period * 0.75, sun(-1.), Easing::EaseIn)
+                .key(period, sun(0.), Easing::Linear),
+        }],
+        looping: true,
     }
-    true
 }
 
 fn sdf(scene: &Scene, ray: &Ray, t: f32) -> Hit {
@@ -30,8 +60,7 @@ fn sdf(scene: &Scene, ray: &Ray, t: f32) -> Hit {
 
     // plane
     //let d1 = plane_sdf(p, vec3(0., 0., 0.), vec3(0., 1., 0.));
-    let fh = -0.1 + 0.5 * ((p.x * 0.5).sin() + (p.z * 0.5).sin());
-    let d1 = p.y - fh;
+    let (d1, terrain_normal) = TERRAIN.sdf(&scene.textures, p, t);
     let mut d2 = 0.0f32;
     let mut d3 = 0.0f32;
     let mut d4 = 0.0f32;
@@ -76,6 +105,10 @@ fn sdf(scene: &Scene, ray: &Ray, t: f32) -> Hit {
         d = d.min(d4);
     }
 
+    if d == d2 || d == d3 {
+        mat = 1;
+    }
+
     if d == d1 || d == d4 {
         col = scene.materials[0].albedo;
         let f = 0.2
@@ -93,10 +126,12 @@ fn sdf(scene: &Scene, ray: &Ray, t: f32) -> Hit {
         dist: d,
         material_index: mat,
         color: col,
+        normal: if d == d1 { Some(terrain_normal) } else { None },
     }
 }
 
-pub fn main() -> Result<(), AppError> {
+/// Builds the scene, loading its textures from `./resources`.
+pub fn scene() -> Result<Scene, AppError> {
     let mut scene = Scene::new(
         vec![
             Material {
@@ -115,6 +150,11 @@ pub fn main() -> Result<(), AppError> {
                 specular: 1.1,
                 albedo: Vec3::new(0.8, 0.6, 0.4),
                 kind: MaterialType::Reflective { roughness: 1. },
+                normal_map: Some(NormalMap::Bump {
+                    texture: 2,
+                    scale: 0.5,
+                    strength: 0.02,
+                }),
                 ..Default::default()
             },
             Material {
@@ -147,6 +187,7 @@ pub fn main() -> Result<(), AppError> {
         direction: vec3(-1., -0.5, -5.).normalize(),
         intensity: 1.,
     })];
+    scene.timeline = timeline();
 
     scene = scene
         .with_texture(ImageUtils::load_image("./resources/chess.png")?)
@@ -154,7 +195,16 @@ pub fn main() -> Result<(), AppError> {
         .with_texture(ImageUtils::load_image("./resources/stone3.jpg")?)
         .with_texture(ImageUtils::load_image("./resources/earth_clouds.jpg")?);
 
-    let mut camera = Camera::new_with_pos(Vec3::new(0., 1., 11.0), Vec3::new(0., 0., -1.));
+    Ok(scene)
+}
+
+pub fn camera() -> Camera {
+    Camera::new_with_pos(Vec3::new(0., 1., 11.0), Vec3::new(0., 0., -1.))
+}
+
+pub fn main() -> Result<(), AppError> {
+    let mut scene = scene()?;
+    let mut camera = camera();
 
     App3D::run(&mut camera, &mut scene)
 }
@@ -6,7 +6,8 @@ use ray_tracing::ray::Ray;
 use ray_tracing::ray_marching::sdfs::{box_sdf, cylinder_sdf, line_sdf, plane_sdf, sphere_sdf};
 use ray_tracing::renderer::Renderer;
 use ray_tracing::scene::{Hit, Scene};
-use ray_tracing::utils::materials::{Material, MaterialType};
+use ray_tracing::sky::{Atmosphere, Sky};
+use ray_tracing::utils::materials::{Displacement, Material, MaterialType, NormalMap};
 use ray_tracing::utils::math;
 use ray_tracing::utils::{errors::AppError, image::ImageUtils};
 
@@ -54,17 +55,19 @@ fn sdf(scene: &Scene, ray: &Ray, t: f32) -> Hit {
         mat = 2;
         col = scene.materials[2].albedo;
     } else if d == d1 {
-       // col = scene.textures[2].from_uv(p.x*0.1, p.z*0.1);
+        // col = scene.textures[2].from_uv(p.x*0.1, p.z*0.1);
     }
 
     Hit {
         dist: d,
         material_index: mat,
         color: col,
+        normal: None,
     }
 }
 
-pub fn main() -> Result<(), AppError> {
+/// Builds the scene, loading its textures from `./resources`.
+pub fn scene() -> Result<Scene, AppError> {
     let mut scene = Scene::new(
         vec![
             Material {
@@ -92,8 +95,19 @@ pub fn main() -> Result<(), AppError> {
                 specular: 2.5,
                 albedo: Vec3::new(0.0, 0.4, 1.),
                 kind: MaterialType::Reflective { roughness: 0.5 },
+                normal_map: Some(NormalMap::Bump {
+                    texture: 1,
+                    scale: 0.5,
+                    strength: 0.01,
+                }),
+                displacement: Some(Displacement {
+                    texture: 1,
+                    scale: 0.5,
+                    amplitude: 0.03,
+                    lipschitz: 4.,
+                }),
                 ..Default::default()
-            }
+            },
         ],
         sdf,
         update,
@@ -104,6 +118,7 @@ pub fn main() -> Result<(), AppError> {
         direction: vec3(-1., -0.5, -5.).normalize(),
         intensity: 1.,
     })];
+    scene.sky = Sky::Atmosphere(Atmosphere::default());
 
     scene = scene
         .with_texture(ImageUtils::load_image("./resources/chess.png")?)
@@ -111,7 +126,16 @@ pub fn main() -> Result<(), AppError> {
         .with_texture(ImageUtils::load_image("./resources/stone3.jpg")?)
         .with_texture(ImageUtils::load_image("./resources/earth_clouds.jpg")?);
 
-    let mut camera = Camera::new_with_pos(Vec3::new(0., 1., 11.0), Vec3::new(0., 0., -1.));
+    Ok(scene)
+}
+
+pub fn camera() -> Camera {
+    Camera::new_with_pos(Vec3::new(0., 1., 11.0), Vec3::new(0., 0., -1.))
+}
+
+pub fn main() -> Result<(), AppError> {
+    let mut scene = scene()?;
+    let mut camera = camera();
 
     App3D::run(&mut camera, &mut scene)
 }
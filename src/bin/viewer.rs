@@ -0,0 +1,10 @@
+use ray_tracing::app::App3D;
+use ray_tracing::utils::errors::AppError;
+
+pub fn main() -> Result<(), AppError> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "./scenes/scene2.scene".to_string());
+
+    App3D::run_file(&path)
+}
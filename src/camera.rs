@@ -1,8 +1,29 @@
-use glam::{ivec2, uvec2, vec2, vec3, Mat4, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
+use glam::{ivec2, uvec2, vec2, vec3, EulerRot, Mat3, Quat, UVec2, Vec2, Vec3};
 
 use crate::utils::math;
 
 static UP: Vec3 = vec3(0., 1., 0.);
+/// Distance from the eye to the image plane, in units of half the image height.
+pub static FOCAL_LENGTH: f32 = 1.5;
+static ORBIT_DISTANCE: f32 = 5.;
+static MIN_ORBIT_DISTANCE: f32 = 0.1;
+static MAX_PITCH: f32 = 89. * math::DEGREES;
+/// Roll rate in radians per second.
+static ROLL_SPEED: f32 = 1.5;
+/// Pan distance per pixel, relative to the orbit distance or the movement speed.
+static PAN_SPEED: f32 = 0.002;
+/// Orbit distance scale per zoom step.
+static ZOOM_FACTOR: f32 = 0.9;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Controller {
+    /// Orbits around `target`; moving zooms and panning shifts the target.
+    Orbit,
+    /// Free flight along the view axes, with roll.
+    Fly,
+    /// Walks on the horizontal plane, with pitch clamped short of the poles.
+    Fps,
+}
 
 #[derive(Debug, Clone)]
 pub struct Camera {
@@ -11,59 +32,177 @@ pub struct Camera {
     pub uu: Vec3,
     pub vv: Vec3,
     pub ww: Vec3,
+    pub controller: Controller,
+    /// Movement speed, in units per second.
+    pub speed: f32,
+    /// Degrees turned per unit of mouse movement.
+    pub rotation_speed: f32,
+    pub target: Vec3,
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
 }
 
 pub enum CameraEvent {
     Resize { w: usize, h: usize },
     RotateXY { delta: Vec2 },
+    Pan { delta: Vec2 },
+    Zoom { delta: f32 },
     Up,
     Down,
     Left,
     Right,
+    Ascend,
+    Descend,
+    RollLeft,
+    RollRight,
 }
 
 impl Camera {
     pub fn new_with_pos(position: Vec3, forward: Vec3) -> Camera {
         let ww = forward.normalize();
-        let uu = ww.cross(vec3(0., 1., 0.)).normalize();
-        let vv = uu.cross(ww).normalize();
 
-        Camera {
+        let mut camera = Camera {
             resolution: vec2(800., 600.),
             position,
-            uu,
-            vv,
+            uu: Vec3::X,
+            vv: UP,
             ww,
+            controller: Controller::Fly,
+            speed: 5.,
+            rotation_speed: 5.,
+            target: position + ww * ORBIT_DISTANCE,
+            yaw: (-ww.x).atan2(-ww.z),
+            pitch: ww.y.clamp(-1., 1.).asin(),
+            roll: 0.,
+        };
+        camera.update_basis();
+        camera
+    }
+
+    /// Rotation taking -Z to the view direction and Y to the camera up vector.
+    pub fn orientation(&self) -> Quat {
+        Quat::from_mat3(&Mat3::from_cols(self.uu, self.vv, -self.ww)).normalize()
+    }
+
+    pub fn set_pose(&mut self, position: Vec3, orientation: Quat) {
+        let distance = self.orbit_distance();
+        (self.yaw, self.pitch, self.roll) = orientation.to_euler(EulerRot::YXZ);
+
+        self.position = position;
+        self.uu = (orientation * Vec3::X).normalize();
+        self.vv = (orientation * Vec3::Y).normalize();
+        self.ww = -(orientation * Vec3::Z).normalize();
+        self.target = position + self.ww * distance;
+    }
+
+    /// Pixel coordinate where `point` is seen, when it lies in front of the camera.
+    pub fn project(&self, point: Vec3) -> Option<Vec2> {
+        let d = point - self.position;
+        let z = d.dot(self.ww);
+        if z <= 0. {
+            return None;
+        }
+        let ratio = self.resolution.x / self.resolution.y;
+        let p = vec2(d.dot(self.uu), d.dot(self.vv)) * FOCAL_LENGTH / z;
+        let p_ndc = vec2((p.x / ratio + 1.) * 0.5, (1. - p.y) * 0.5);
+        Some(p_ndc * self.resolution)
+    }
+
+    pub fn set_controller(&mut self, controller: Controller) {
+        if controller == Controller::Orbit {
+            self.target = self.position + self.ww * ORBIT_DISTANCE;
+        }
+        if controller != Controller::Fly {
+            self.roll = 0.;
+        }
+        self.controller = controller;
+        self.update_basis();
+    }
+
+    fn orbit_distance(&self) -> f32 {
+        (self.target - self.position)
+            .length()
+            .max(MIN_ORBIT_DISTANCE)
+    }
+
+    /// Recomputes the view axes from yaw, pitch and roll, and for the orbit
+    /// controller the position around the target.
+    fn update_basis(&mut self) {
+        if self.controller != Controller::Fly {
+            self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        let distance = self.orbit_distance();
+        let orientation = Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, self.roll);
+
+        self.uu = (orientation * Vec3::X).normalize();
+        self.vv = (orientation * Vec3::Y).normalize();
+        self.ww = -(orientation * Vec3::Z).normalize();
+
+        if self.controller == Controller::Orbit {
+            self.position = self.target - self.ww * distance;
         }
     }
 
     pub fn update(&mut self, events: &Vec<CameraEvent>, ts: f32) {
-        let speed = 5.;
-        let rotation_speed = 5.;
+        let step = self.speed * ts;
+        let turn = self.rotation_speed * math::DEGREES;
+        let distance = self.orbit_distance();
+
+        // Forward and right directions of travel.
+        let forward = match self.controller {
+            Controller::Fps => vec3(self.ww.x, 0., self.ww.z).normalize_or_zero(),
+            _ => self.ww,
+        };
+        let right = self.uu;
+
         for event in events {
-            match event {
-                CameraEvent::Up => self.position += self.ww * speed * ts,
-                CameraEvent::Down => self.position -= self.ww * speed * ts,
-                CameraEvent::Left => self.position -= self.uu * speed * ts,
-                CameraEvent::Right => self.position += self.uu * speed * ts,
-                CameraEvent::Resize { w, h } => {
+            match (event, self.controller) {
+                (CameraEvent::Resize { w, h }, _) => {
                     self.resolution = vec2(*w as f32, *h as f32);
                 }
+                (CameraEvent::RotateXY { delta }, _) => {
+                    self.yaw -= delta.x * turn;
+                    self.pitch += delta.y * turn;
+                }
+                (CameraEvent::Pan { delta }, Controller::Orbit) => {
+                    let scale = distance * PAN_SPEED;
+                    self.target += (self.vv * delta.y - self.uu * delta.x) * scale;
+                }
+                (CameraEvent::Pan { delta }, _) => {
+                    self.position +=
+                        (self.vv * delta.y - self.uu * delta.x) * self.speed * PAN_SPEED;
+                }
+                (CameraEvent::Zoom { delta }, Controller::Orbit) => {
+                    self.position = self.target - self.ww * distance * ZOOM_FACTOR.powf(*delta);
+                }
+                (CameraEvent::Zoom { delta }, _) => {
+                    self.position += self.ww * *delta * self.speed * 0.1
+                }
 
-                CameraEvent::RotateXY { delta } => {
-                    let pitch_delta = delta.y * rotation_speed;
-                    let yaw_delta = -delta.x * rotation_speed;
-
-                    let rotation = Mat4::from_rotation_x(pitch_delta as f32 * math::DEGREES)
-                        * Mat4::from_rotation_y(yaw_delta as f32 * math::DEGREES);
-
-                    let fd = rotation * Vec4::new(self.ww.x, self.ww.y, self.ww.z, 1.);
-
-                    self.ww = fd.xyz().normalize();
-                    self.uu = self.ww.cross(vec3(0., 1., 0.)).normalize();
-                    self.vv = self.uu.cross(self.ww).normalize();
+                (CameraEvent::Up, Controller::Orbit) => {
+                    self.position =
+                        self.target - self.ww * (distance - step).max(MIN_ORBIT_DISTANCE)
                 }
+                (CameraEvent::Down, Controller::Orbit) => {
+                    self.position = self.target - self.ww * (distance + step)
+                }
+                (CameraEvent::Left, Controller::Orbit) => self.yaw -= step / distance,
+                (CameraEvent::Right, Controller::Orbit) => self.yaw += step / distance,
+                (CameraEvent::Ascend, Controller::Orbit) => self.target += self.vv * step,
+                (CameraEvent::Descend, Controller::Orbit) => self.target -= self.vv * step,
+
+                (CameraEvent::Up, _) => self.position += forward * step,
+                (CameraEvent::Down, _) => self.position -= forward * step,
+                (CameraEvent::Left, _) => self.position -= right * step,
+                (CameraEvent::Right, _) => self.position += right * step,
+                (CameraEvent::Ascend, Controller::Fly) => self.position += self.vv * step,
+                (CameraEvent::Descend, Controller::Fly) => self.position -= self.vv * step,
+                (CameraEvent::RollLeft, Controller::Fly) => self.roll += ROLL_SPEED * ts,
+                (CameraEvent::RollRight, Controller::Fly) => self.roll -= ROLL_SPEED * ts,
+                _ => {}
             }
         }
+        self.update_basis();
     }
 }
@@ -0,0 +1,151 @@
+use std::fs;
+
+use glam::{Quat, Vec3};
+
+use crate::camera::Camera;
+use crate::utils::errors::AppError;
+
+/// Camera pose at a point in time.
+#[derive(Debug, Copy, Clone)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub position: Vec3,
+    pub orientation: Quat,
+}
+
+/// Keyframed camera flythrough, with Catmull-Rom interpolated positions and
+/// slerped orientations.
+#[derive(Debug, Clone, Default)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+    pub looping: bool,
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2. * p1
+        + (p2 - p0) * t
+        + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+        + (3. * p1 - p0 - 3. * p2 + p3) * t3)
+}
+
+impl CameraKeyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            position: camera.position,
+            orientation: camera.orientation(),
+        }
+    }
+}
+
+impl CameraPath {
+    /// Inserts a keyframe, keeping them ordered by time.
+    pub fn add(&mut self, keyframe: CameraKeyframe) {
+        let i = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(i, keyframe);
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |k| k.time)
+    }
+
+    /// Interpolated position and orientation at `time`, clamped to the path
+    /// ends unless it loops.
+    pub fn sample(&self, time: f32) -> Option<(Vec3, Quat)> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+
+        let span = last.time - first.time;
+        let time = if self.looping && span > 0. {
+            first.time + (time - first.time).rem_euclid(span)
+        } else {
+            time.clamp(first.time, last.time)
+        };
+
+        if keys.len() == 1 {
+            return Some((first.position, first.orientation));
+        }
+        let i = keys
+            .partition_point(|k| k.time <= time)
+            .clamp(1, keys.len() - 1)
+            - 1;
+
+        let k1 = &keys[i];
+        let k2 = &keys[i + 1];
+        let k0 = &keys[i.saturating_sub(1)];
+        let k3 = &keys[(i + 2).min(keys.len() - 1)];
+
+        let t = ((time - k1.time) / (k2.time - k1.time).max(1e-6)).clamp(0., 1.);
+        let position = catmull_rom(k0.position, k1.position, k2.position, k3.position, t);
+        let orientation = k1.orientation.slerp(k2.orientation, t).normalize();
+        Some((position, orientation))
+    }
+
+    /// Moves the camera to the path pose at `time`; returns false for an empty path.
+    pub fn apply(&self, camera: &mut Camera, time: f32) -> bool {
+        match self.sample(time) {
+            Some((position, orientation)) => {
+                camera.set_pose(position, orientation);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Writes one `time px py pz qx qy qz qw` line per keyframe.
+    pub fn save(&self, path: &str) -> Result<(), AppError> {
+        let mut out = String::from("# time position.xyz orientation.xyzw\n");
+        if self.looping {
+            out.push_str("loop\n");
+        }
+        for k in &self.keyframes {
+            let (p, q) = (k.position, k.orientation);
+            out.push_str(&format!(
+                "{} {} {} {} {} {} {} {}\n",
+                k.time, p.x, p.y, p.z, q.x, q.y, q.z, q.w
+            ));
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<CameraPath, AppError> {
+        let text = fs::read_to_string(path)?;
+        let mut camera_path = CameraPath::default();
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "loop" {
+                camera_path.looping = true;
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| AppError::ErrorString(format!("{}:{}: {}", path, n + 1, e)))?;
+            let [time, px, py, pz, qx, qy, qz, qw] = values[..] else {
+                return Err(AppError::ErrorString(format!(
+                    "{}:{}: expected 8 numbers, found {}",
+                    path,
+                    n + 1,
+                    values.len()
+                )));
+            };
+
+            camera_path.add(CameraKeyframe {
+                time,
+                position: Vec3::new(px, py, pz),
+                orientation: Quat::from_xyzw(qx, qy, qz, qw).normalize(),
+            });
+        }
+        Ok(camera_path)
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use glam::{vec2, Vec3};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::camera::Camera;
+use crate::camera_path::CameraPath;
+use crate::renderer::Renderer;
+use crate::scene::Scene;
+use crate::sky::Sky;
+use crate::utils::errors::AppError;
+use crate::utils::image::ImageUtils;
+
+/// Auxiliary image written next to a render.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AovPass {
+    /// Primary hit depth, normalized by the scene's max distance.
+    Depth,
+    /// Normals mapped to [0, 1].
+    Normal,
+}
+
+impl AovPass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AovPass::Depth => "depth",
+            AovPass::Normal => "normal",
+        }
+    }
+}
+
+/// Saves the displayed frame, optionally with its AOVs and a text sidecar
+/// describing the camera and scene.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub directory: String,
+    pub aovs: bool,
+    pub sidecar: bool,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            directory: "./captures".to_string(),
+            aovs: true,
+            sidecar: true,
+        }
+    }
+}
+
+impl Capture {
+    /// Writes `img`, an RGBA buffer at the camera resolution, and returns the
+    /// path of the saved image.
+    pub fn screenshot(
+        &self,
+        img: &[u8],
+        scene: &Scene,
+        camera: &Camera,
+        renderer: &Renderer,
+        time: f32,
+    ) -> Result<String, AppError> {
+        fs::create_dir_all(&self.directory)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let base = format!("{}/shot_{}", self.directory, millis);
+        let (w, h) = (camera.resolution.x as u32, camera.resolution.y as u32);
+
+        let path = format!("{}.png", base);
+        ImageUtils::save_rgba(&path, w, h, img)?;
+        if self.aovs {
+            Self::save_aovs(&base, scene, camera, &[AovPass::Depth, AovPass::Normal])?;
+        }
+        if self.sidecar {
+            fs::write(
+                format!("{}.txt", base),
+                Self::sidecar(scene, camera, renderer, time),
+            )?;
+        }
+        Ok(path)
+    }
+
+    /// Saves the selected AOVs of the primary hits as `<base>_<name>.png`.
+    pub fn save_aovs(
+        base: &str,
+        scene: &Scene,
+        camera: &Camera,
+        passes: &[AovPass],
+    ) -> Result<(), AppError> {
+        if passes.is_empty() {
+            return Ok(());
+        }
+        let (w, h) = (camera.resolution.x as u32, camera.resolution.y as u32);
+        let aovs: Vec<_> = (0..w * h)
+            .into_par_iter()
+            .map(|i| {
+                let coord = vec2((i % w) as f32 + 0.5, (i / w) as f32 + 0.5);
+                scene.aov(camera, coord)
+            })
+            .collect();
+
+        for pass in passes {
+            let path = format!("{}_{}.png", base, pass.name());
+            match pass {
+                AovPass::Depth => {
+                    let depth: Vec<f32> = aovs
+                        .iter()
+                        .map(|a| (a.depth / scene.max_distance).min(1.))
+                        .collect();
+                    ImageUtils::save_gray16(&path, w, h, &depth)?;
+                }
+                AovPass::Normal => {
+                    let normals: Vec<Vec3> = aovs.iter().map(|a| a.normal * 0.5 + 0.5).collect();
+                    ImageUtils::save_rgb(&path, w, h, &normals)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sidecar(scene: &Scene, camera: &Camera, renderer: &Renderer, time: f32) -> String {
+        let v = |v: Vec3| format!("{} {} {}", v.x, v.y, v.z);
+        let sky = match &scene.sky {
+            Sky::Gradient => "gradient".to_string(),
+            Sky::Constant(c) => format!("constant {}", v(*c)),
+            Sky::Atmosphere(a) => format!("atmosphere {}", a.turbidity),
+            Sky::Environment(env) => format!("environment {}", env.path),
+        };
+
+        [
+            format!("time = {}", time),
+            format!("position = {}", v(camera.position)),
+            format!("forward = {}", v(camera.ww)),
+            format!("right = {}", v(camera.uu)),
+            format!("up = {}", v(camera.vv)),
+            format!(
+                "resolution = {} {}",
+                camera.resolution.x, camera.resolution.y
+            ),
+            format!("mode = {:?}", renderer.mode),
+            format!("samples = {}", renderer.samples()),
+            format!("sky = {}", sky),
+            format!("sun = {}", v(scene.sun_direction())),
+            format!("max_distance = {}", scene.max_distance),
+            format!("medium = {}", scene.medium.is_some()),
+            format!("volumes = {}", scene.volumes.len()),
+        ]
+        .join("\n")
+            + "\n"
+    }
+}
+
+/// Writes a numbered PNG per frame while the scene advances by a fixed
+/// timestep, however long each frame takes to render.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    pub directory: String,
+    /// Simulated seconds between two frames.
+    pub timestep: f32,
+    /// Samples accumulated per frame in path traced mode.
+    pub samples: u32,
+    recording: bool,
+    frame: u32,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            directory: "./recording".to_string(),
+            timestep: 1. / 30.,
+            samples: 16,
+            recording: false,
+            frame: 0,
+        }
+    }
+}
+
+impl Recorder {
+    pub fn start(&mut self) -> Result<(), AppError> {
+        fs::create_dir_all(&self.directory)?;
+        self.recording = true;
+        self.frame = 0;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.recording = false;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Simulated time of the frame being rendered.
+    pub fn time(&self) -> f32 {
+        self.frame as f32 * self.timestep
+    }
+
+    /// Renders the whole camera path offline, one frame per timestep.
+    pub fn record_path(
+        &mut self,
+        renderer: &mut Renderer,
+        scene: &mut Scene,
+        camera: &mut Camera,
+        path: &CameraPath,
+    ) -> Result<(), AppError> {
+        self.start()?;
+        while self.time() <= path.duration() {
+            let time = self.time();
+            path.apply(camera, time);
+            scene.animate(time);
+            let img = renderer.render_image(scene, camera, self.samples);
+            self.save_frame(&img, camera)?;
+        }
+        self.stop();
+        Ok(())
+    }
+
+    /// Saves the finished frame and moves on to the next one.
+    pub fn save_frame(&mut self, img: &[u8], camera: &Camera) -> Result<(), AppError> {
+        let path = format!("{}/frame_{:05}.png", self.directory, self.frame);
+        let (w, h) = (camera.resolution.x as u32, camera.resolution.y as u32);
+        ImageUtils::save_rgba(&path, w, h, img)?;
+        self.frame += 1;
+        Ok(())
+    }
+}
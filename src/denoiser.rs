@@ -0,0 +1,109 @@
+use glam::Vec3;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::scene::Aov;
+
+/// 1D B3 spline weights for offsets 0, 1 and 2.
+static KERNEL: [f32; 3] = [3. / 8., 1. / 4., 1. / 16.];
+
+/// Edge-aware à-trous wavelet filter guided by the depth and normal AOVs.
+#[derive(Debug, Copy, Clone)]
+pub struct Denoiser {
+    /// Number of passes; pass `i` samples pixels `2^i` apart.
+    pub iterations: usize,
+    /// Color difference tolerated between neighbours, halved at each pass.
+    pub sigma_color: f32,
+    /// Exponent applied to the normal similarity.
+    pub sigma_normal: f32,
+    /// Depth difference tolerated between neighbours, relative to the pixel depth.
+    pub sigma_depth: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 4,
+            sigma_color: 0.8,
+            sigma_normal: 64.,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn denoise(&self, colors: &[Vec3], aovs: &[Aov], width: usize) -> Vec<Vec3> {
+        let mut current = colors.to_vec();
+        let mut sigma_color = self.sigma_color;
+
+        for i in 0..self.iterations {
+            let step = 1 << i;
+            let source = &current;
+            current = (0..source.len())
+                .into_par_iter()
+                .map(|p| self.filter(source, aovs, width, p, step, sigma_color))
+                .collect();
+            sigma_color *= 0.5;
+        }
+        current
+    }
+
+    fn filter(
+        &self,
+        colors: &[Vec3],
+        aovs: &[Aov],
+        width: usize,
+        p: usize,
+        step: i32,
+        sigma_color: f32,
+    ) -> Vec3 {
+        let center = &aovs[p];
+        let color = colors[p];
+        if !center.depth.is_finite() {
+            return color;
+        }
+
+        let height = (colors.len() / width) as i32;
+        let (x, y) = ((p % width) as i32, (p / width) as i32);
+        let depth_scale = self.sigma_depth * center.depth * step as f32;
+
+        let mut sum = Vec3::ZERO;
+        let mut weights = 0.;
+        for dy in -2..=2i32 {
+            let qy = y + dy * step;
+            if qy < 0 || qy >= height {
+                continue;
+            }
+            for dx in -2..=2i32 {
+                let qx = x + dx * step;
+                if qx < 0 || qx >= width as i32 {
+                    continue;
+                }
+                let q = qy as usize * width + qx as usize;
+                let aov = &aovs[q];
+                if !aov.depth.is_finite() {
+                    continue;
+                }
+
+                let h = KERNEL[dx.unsigned_abs() as usize] * KERNEL[dy.unsigned_abs() as usize];
+                let w_normal = center
+                    .normal
+                    .dot(aov.normal)
+                    .max(0.)
+                    .powf(self.sigma_normal);
+                let w_depth = (-(center.depth - aov.depth).abs() / depth_scale).exp();
+                let w_color =
+                    (-(color - colors[q]).length_squared() / (sigma_color * sigma_color)).exp();
+
+                let w = h * w_normal * w_depth * w_color;
+                sum += colors[q] * w;
+                weights += w;
+            }
+        }
+
+        if weights > 0. {
+            sum / weights
+        } else {
+            color
+        }
+    }
+}
@@ -1,8 +1,20 @@
 pub mod app;
 pub mod camera;
+pub mod camera_path;
 pub mod ray;
 pub mod renderer;
+pub mod denoiser;
+pub mod resolution;
+pub mod tiles;
+pub mod overlay;
+pub mod capture;
 pub mod scene;
+pub mod scene_file;
+pub mod timeline;
 pub mod light;
+pub mod sky;
+pub mod media;
+pub mod volume;
 pub mod utils;
+pub mod mesh;
 pub mod ray_marching;
\ No newline at end of file
@@ -0,0 +1,145 @@
+use std::f32::consts::PI;
+
+use glam::Vec3;
+
+use crate::light::{Light, LightSource};
+use crate::ray::Ray;
+use crate::ray_marching::RayMarching;
+use crate::scene::Scene;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Density {
+    Homogeneous(f32),
+    /// Exponential fog: `density` at height `base`, thinning by `falloff` per unit up.
+    Height {
+        density: f32,
+        base: f32,
+        falloff: f32,
+    },
+}
+
+/// Participating medium filling the whole scene, rendered with single scattering.
+#[derive(Debug, Copy, Clone)]
+pub struct Medium {
+    pub density: Density,
+    /// Absorption coefficient per unit density.
+    pub absorption: Vec3,
+    /// Scattering coefficient per unit density.
+    pub scattering: Vec3,
+    /// Henyey-Greenstein asymmetry, positive values scatter forward.
+    pub anisotropy: f32,
+    pub steps: usize,
+    /// How far rays that hit nothing are marched through the medium.
+    pub max_distance: f32,
+    /// Shadow the in-scattered light with the scene SDF (god rays).
+    pub shadows: bool,
+}
+
+impl Default for Medium {
+    fn default() -> Self {
+        Self {
+            density: Density::Homogeneous(0.02),
+            absorption: Vec3::splat(0.1),
+            scattering: Vec3::splat(0.9),
+            anisotropy: 0.3,
+            steps: 32,
+            max_distance: 40.,
+            shadows: true,
+        }
+    }
+}
+
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denom = 1. + g * g - 2. * g * cos_theta;
+    (1. - g * g) / (4. * PI * denom * denom.sqrt())
+}
+
+impl Density {
+    pub fn at(&self, p: Vec3) -> f32 {
+        match *self {
+            Density::Homogeneous(d) => d,
+            Density::Height {
+                density,
+                base,
+                falloff,
+            } => density * (-(p.y - base).max(0.) * falloff).exp(),
+        }
+    }
+}
+
+impl Medium {
+    fn light_visibility(&self, rm: &RayMarching, p: Vec3, l: &Light) -> f32 {
+        if !self.shadows {
+            return 1.;
+        }
+        rm.shadow(
+            &Ray {
+                origin: p,
+                direction: -l.direction(p),
+            },
+            32.,
+        )
+    }
+
+    /// Integrates the medium along `ray` up to `t_max`. Returns the transmittance
+    /// and the light scattered towards the ray origin. `ambient` is the sky light
+    /// scattered isotropically, `jitter` in [0, 1) offsets the samples to trade
+    /// banding for noise.
+    pub fn integrate(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        t_max: f32,
+        ambient: Vec3,
+        jitter: f32,
+    ) -> (Vec3, Vec3) {
+        let rm = RayMarching { scene };
+        let t_max = t_max.min(self.max_distance);
+        let dt = t_max / self.steps as f32;
+
+        let mut transmittance = Vec3::ONE;
+        let mut scattered = Vec3::ZERO;
+
+        for i in 0..self.steps {
+            let t = (i as f32 + jitter) * dt;
+            let p = ray.origin + ray.direction * t;
+            let density = self.density.at(p);
+            if density <= 0. {
+                continue;
+            }
+
+            let sigma_s = self.scattering * density;
+            let sigma_t = (self.absorption + self.scattering) * density;
+
+            let mut light = ambient;
+            for l in &scene.lights {
+                let phase = henyey_greenstein(ray.direction.dot(-l.direction(p)), self.anisotropy);
+                light +=
+                    l.albedo() * l.intensity() * phase * 4. * PI * self.light_visibility(&rm, p, l);
+            }
+
+            // Energy conserving integration of the in-scattering over the step.
+            let step_transmittance = (-sigma_t * dt).exp();
+            let s = sigma_s * light;
+            scattered +=
+                transmittance * (s - s * step_transmittance) / sigma_t.max(Vec3::splat(1e-6));
+            transmittance *= step_transmittance;
+        }
+
+        (transmittance, scattered)
+    }
+
+    /// Composites `color`, seen `t` units along `ray`, through the medium.
+    pub fn apply(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        t: f32,
+        color: Vec3,
+        ambient: Vec3,
+        jitter: f32,
+    ) -> Vec3 {
+        let (transmittance, scattered) = self.integrate(scene, ray, t, ambient, jitter);
+        color * transmittance + scattered
+    }
+}
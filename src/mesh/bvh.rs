@@ -0,0 +1,177 @@
+use glam::Vec3;
+
+static LEAF_SIZE: usize = 4;
+/// Nodes waiting to be visited by `nearest`. Median splits keep the tree
+/// depth below 32 for `u32` indices, and at most one node per level waits.
+const STACK_SIZE: usize = 64;
+
+/// Axis aligned bounding box.
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::INFINITY,
+        max: Vec3::NEG_INFINITY,
+    };
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points.iter().fold(Aabb::EMPTY, |b, p| b.grow(*p))
+    }
+
+    pub fn grow(&self, p: Vec3) -> Aabb {
+        Aabb {
+            min: self.min.min(p),
+            max: self.max.max(p),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Box grown by `margin` on every side.
+    pub fn expand(&self, margin: f32) -> Aabb {
+        Aabb {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    /// Distance from `p` to the box, zero inside.
+    pub fn distance(&self, p: Vec3) -> f32 {
+        (self.min - p).max(p - self.max).max(Vec3::ZERO).length()
+    }
+}
+
+/// Interior nodes have `count == 0` and their children at `first` and
+/// `first + 1`, leaves cover `indices[first..first + count]`.
+#[derive(Debug, Copy, Clone)]
+pub struct BvhNode {
+    pub bounds: Aabb,
+    pub first: u32,
+    pub count: u32,
+}
+
+/// Bounding volume hierarchy over primitives given by their boxes, split at
+/// the median centroid of the longest axis.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    pub indices: Vec<u32>,
+}
+
+impl Bvh {
+    pub fn build(boxes: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: vec![BvhNode {
+                bounds: Aabb::EMPTY,
+                first: 0,
+                count: boxes.len() as u32,
+            }],
+            indices: (0..boxes.len() as u32).collect(),
+        };
+        bvh.split(0, boxes);
+        bvh
+    }
+
+    fn split(&mut self, node: usize, boxes: &[Aabb]) {
+        let (first, count) = (
+            self.nodes[node].first as usize,
+            self.nodes[node].count as usize,
+        );
+        let items = &mut self.indices[first..first + count];
+        self.nodes[node].bounds = items
+            .iter()
+            .fold(Aabb::EMPTY, |b, i| b.union(&boxes[*i as usize]));
+        if count <= LEAF_SIZE {
+            return;
+        }
+
+        let centroids = items
+            .iter()
+            .fold(Aabb::EMPTY, |b, i| b.grow(boxes[*i as usize].center()));
+        let size = centroids.size();
+        let axis = if size.x > size.y && size.x > size.z {
+            0
+        } else if size.y > size.z {
+            1
+        } else {
+            2
+        };
+        let half = count / 2;
+        items.select_nth_unstable_by(half, |a, b| {
+            let a = boxes[*a as usize].center()[axis];
+            let b = boxes[*b as usize].center()[axis];
+            a.total_cmp(&b)
+        });
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds: Aabb::EMPTY,
+            first: first as u32,
+            count: half as u32,
+        });
+        self.nodes.push(BvhNode {
+            bounds: Aabb::EMPTY,
+            first: (first + half) as u32,
+            count: (count - half) as u32,
+        });
+        self.nodes[node].first = left as u32;
+        self.nodes[node].count = 0;
+        self.split(left, boxes);
+        self.split(left + 1, boxes);
+    }
+
+    /// Finds the primitive closest to `p`, given a function returning the
+    /// squared distance from `p` to a primitive.
+    pub fn nearest(&self, p: Vec3, distance2: impl Fn(usize) -> f32) -> Option<(usize, f32)> {
+        let mut best: Option<(usize, f32)> = None;
+        let mut best_d2 = f32::MAX;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let n = stack[len];
+            let node = &self.nodes[n];
+            if node.bounds.distance(p).powi(2) >= best_d2 {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.first as usize;
+                for &i in &self.indices[first..first + node.count as usize] {
+                    let d2 = distance2(i as usize);
+                    if d2 < best_d2 {
+                        best_d2 = d2;
+                        best = Some((i as usize, d2));
+                    }
+                }
+            } else {
+                // Visits the nearer child first, so it can prune the other.
+                let (a, b) = (node.first as usize, node.first as usize + 1);
+                let da = self.nodes[a].bounds.distance(p);
+                let db = self.nodes[b].bounds.distance(p);
+                let (near, far) = if da < db { (a, b) } else { (b, a) };
+                stack[len] = far;
+                stack[len + 1] = near;
+                len += 2;
+            }
+        }
+        best
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::path::Path;
+
+use glam::Vec3;
+
+use crate::ray::Ray;
+use crate::ray_marching::RayMarching;
+use crate::scene::Scene;
+use crate::utils::errors::AppError;
+
+/// Indexed triangle mesh produced by polygonizing a scene.
+#[derive(Debug, Clone, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    /// Index of the scene material found at each vertex.
+    pub materials: Vec<usize>,
+    pub faces: Vec<[u32; 3]>,
+}
+
+impl TriangleMesh {
+    /// Fills in the normals and materials of the vertices from the scene.
+    pub fn shade(&mut self, scene: &Scene) {
+        let rm = RayMarching { scene };
+        self.normals = self.positions.iter().map(|p| rm.normal(*p)).collect();
+        self.materials = self
+            .positions
+            .iter()
+            .map(|p| {
+                let ray = Ray {
+                    origin: *p,
+                    direction: Vec3::Z,
+                };
+                rm.distance(&ray, 0.).material_index
+            })
+            .collect();
+    }
+
+    fn face_normal(&self, f: &[u32; 3]) -> Vec3 {
+        let [a, b, c] = f.map(|i| self.positions[i as usize]);
+        (b - a).cross(c - a).normalize_or_zero()
+    }
+
+    /// Material colors of the vertices.
+    fn colors(&self, scene: &Scene) -> Vec<[u8; 3]> {
+        self.materials
+            .iter()
+            .map(|m| {
+                let c = scene.materials[*m].albedo.clamp(Vec3::ZERO, Vec3::ONE) * 255.;
+                [c.x as u8, c.y as u8, c.z as u8]
+            })
+            .collect()
+    }
+
+    /// Writes `.obj`, `.ply` or `.stl` depending on the extension of `path`.
+    pub fn save(&self, path: &str, scene: &Scene) -> Result<(), AppError> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("obj") => self.save_obj(path, scene),
+            Some("ply") => self.save_ply(path, scene),
+            Some("stl") => self.save_stl(path),
+            _ => Err(AppError::ErrorString(format!(
+                "{}: unsupported mesh format",
+                path
+            ))),
+        }
+    }
+
+    /// Vertex colors are written after the positions, an extension most
+    /// tools read.
+    pub fn save_obj(&self, path: &str, scene: &Scene) -> Result<(), AppError> {
+        let mut out = String::new();
+        for (p, c) in self.positions.iter().zip(self.colors(scene)) {
+            out.push_str(&format!(
+                "v {} {} {} {:.4} {:.4} {:.4}\n",
+                p.x,
+                p.y,
+                p.z,
+                c[0] as f32 / 255.,
+                c[1] as f32 / 255.,
+                c[2] as f32 / 255.
+            ));
+        }
+        for n in &self.normals {
+            out.push_str(&format!("vn {} {} {}\n", n.x, n.y, n.z));
+        }
+        for f in &self.faces {
+            let [a, b, c] = f.map(|i| i + 1);
+            out.push_str(&format!("f {a}//{a} {b}//{b} {c}//{c}\n"));
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Binary little endian PLY with normals, colors and material ids.
+    pub fn save_ply(&self, path: &str, scene: &Scene) -> Result<(), AppError> {
+        let header = format!(
+            "ply\n\
+             format binary_little_endian 1.0\n\
+             element vertex {}\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property float nx\nproperty float ny\nproperty float nz\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             property int material\n\
+             element face {}\n\
+             property list uchar int vertex_indices\n\
+             end_header\n",
+            self.positions.len(),
+            self.faces.len()
+        );
+        let mut out = header.into_bytes();
+        let colors = self.colors(scene);
+        for (i, color) in colors.iter().enumerate() {
+            for v in self.positions[i]
+                .to_array()
+                .iter()
+                .chain(&self.normals[i].to_array())
+            {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.extend_from_slice(color);
+            out.extend_from_slice(&(self.materials[i] as i32).to_le_bytes());
+        }
+        for f in &self.faces {
+            out.push(3);
+            for i in f {
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Binary STL, which only keeps the facet normals.
+    pub fn save_stl(&self, path: &str) -> Result<(), AppError> {
+        let mut out = vec![0u8; 80];
+        out.extend_from_slice(&(self.faces.len() as u32).to_le_bytes());
+        for f in &self.faces {
+            let n = self.face_normal(f);
+            let vertices = f.map(|i| self.positions[i as usize]);
+            for v in std::iter::once(&n).chain(&vertices) {
+                for x in v.to_array() {
+                    out.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+            out.extend_from_slice(&[0, 0]);
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+
+use glam::{vec3, Vec3};
+
+use crate::utils::errors::AppError;
+
+/// Indexed triangle soup read from a mesh file.
+pub struct RawMesh {
+    pub vertices: Vec<Vec3>,
+    pub faces: Vec<[u32; 3]>,
+}
+
+fn parse_error(path: &str, line: usize, message: impl AsRef<str>) -> AppError {
+    AppError::ErrorString(format!("{}:{}: {}", path, line, message.as_ref()))
+}
+
+/// Reads `v` and `f` statements of a Wavefront OBJ file. Polygons are
+/// triangulated as fans, other statements are ignored.
+pub fn load_obj(path: &str) -> Result<RawMesh, AppError> {
+    let text = fs::read_to_string(path)?;
+    let mut vertices = vec![];
+    let mut faces = vec![];
+
+    for (n, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let v = tokens
+                    .take(3)
+                    .map(|t| t.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| parse_error(path, n + 1, e.to_string()))?;
+                let [x, y, z] = v[..] else {
+                    return Err(parse_error(path, n + 1, "vertex needs 3 coordinates"));
+                };
+                vertices.push(vec3(x, y, z));
+            }
+            Some("f") => {
+                // Only the position index of `v/vt/vn` is used. Negative
+                // indices count back from the last vertex.
+                let polygon = tokens
+                    .map(|t| {
+                        let i = t.split('/').next().unwrap_or("").parse::<i64>().ok()?;
+                        let i = if i < 0 {
+                            vertices.len() as i64 + i
+                        } else {
+                            i - 1
+                        };
+                        (0..vertices.len() as i64).contains(&i).then_some(i as u32)
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| parse_error(path, n + 1, "invalid face index"))?;
+                if polygon.len() < 3 {
+                    return Err(parse_error(path, n + 1, "face needs 3 vertices"));
+                }
+                for i in 1..polygon.len() - 1 {
+                    faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(RawMesh { vertices, faces })
+}
+
+/// Reads binary or ASCII STL, welding the vertices shared by facets.
+pub fn load_stl(path: &str) -> Result<RawMesh, AppError> {
+    let bytes = fs::read(path)?;
+    let triangles = if is_binary_stl(&bytes) {
+        binary_stl(&bytes)
+    } else {
+        ascii_stl(path, &String::from_utf8_lossy(&bytes))?
+    };
+
+    let mut index: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut vertices = vec![];
+    let faces = triangles
+        .iter()
+        .map(|t| {
+            t.map(|v| {
+                *index
+                    .entry(v.to_array().map(f32::to_bits))
+                    .or_insert_with(|| {
+                        vertices.push(v);
+                        vertices.len() as u32 - 1
+                    })
+            })
+        })
+        .collect();
+    Ok(RawMesh { vertices, faces })
+}
+
+/// Binary files have an 80 byte header, a facet count and 50 bytes per
+/// facet. Some exporters start those headers with "solid" too, so the size
+/// decides.
+fn is_binary_stl(bytes: &[u8]) -> bool {
+    if bytes.len() < 84 {
+        return false;
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    bytes.len() == 84 + count * 50
+}
+
+fn binary_stl(bytes: &[u8]) -> Vec<[Vec3; 3]> {
+    let float = |b: &[u8], o: usize| f32::from_le_bytes([b[o], b[o + 1], b[o + 2], b[o + 3]]);
+    bytes[84..]
+        .chunks_exact(50)
+        .map(|facet| {
+            // Skips the facet normal, the winding gives the orientation.
+            [12, 24, 36].map(|o| vec3(float(facet, o), float(facet, o + 4), float(facet, o + 8)))
+        })
+        .collect()
+}
+
+fn ascii_stl(path: &str, text: &str) -> Result<Vec<[Vec3; 3]>, AppError> {
+    let mut triangles = vec![];
+    let mut facet = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("vertex") => {
+                let v = tokens
+                    .map(|t| t.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| parse_error(path, n + 1, e.to_string()))?;
+                let [x, y, z] = v[..] else {
+                    return Err(parse_error(path, n + 1, "vertex needs 3 coordinates"));
+                };
+                facet.push(vec3(x, y, z));
+            }
+            Some("endloop") => {
+                if facet.len() != 3 {
+                    return Err(parse_error(path, n + 1, "facet is not a triangle"));
+                }
+                triangles.push([facet[0], facet[1], facet[2]]);
+                facet.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
@@ -0,0 +1,352 @@
+pub mod bvh;
+pub mod export;
+pub mod import;
+pub mod polygonize;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use glam::Vec3;
+
+use crate::ray_marching::distance_grid::DistanceGrid;
+use crate::utils::errors::AppError;
+
+use bvh::{Aabb, Bvh};
+use import::RawMesh;
+
+/// Beyond this fraction of the mesh diagonal the distance to the bounding box
+/// is returned instead, which never overshoots the surface.
+static FAR_FIELD: f32 = 0.1;
+
+/// Part of a triangle the closest point lies on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Feature {
+    Vertex(usize),
+    /// Edge from vertex `i` to vertex `i + 1`.
+    Edge(usize),
+    Face,
+}
+
+/// Triangle with the angle weighted pseudo-normals of its vertices and edges,
+/// which give the sign of the distance whatever feature is closest.
+#[derive(Debug, Copy, Clone)]
+pub struct MeshTriangle {
+    pub vertices: [Vec3; 3],
+    pub normal: Vec3,
+    vertex_normals: [Vec3; 3],
+    edge_normals: [Vec3; 3],
+}
+
+impl MeshTriangle {
+    fn bounds(&self) -> Aabb {
+        Aabb::from_points(&self.vertices)
+    }
+
+    /// Closest point to `p` (Ericson, Real-Time Collision Detection 5.1.5).
+    fn closest_point(&self, p: Vec3) -> (Vec3, Feature) {
+        let [a, b, c] = self.vertices;
+        let ab = b - a;
+        let ac = c - a;
+        let ap = p - a;
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0. && d2 <= 0. {
+            return (a, Feature::Vertex(0));
+        }
+
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0. && d4 <= d3 {
+            return (b, Feature::Vertex(1));
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0. && d1 >= 0. && d3 <= 0. {
+            return (a + ab * (d1 / (d1 - d3)), Feature::Edge(0));
+        }
+
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0. && d5 <= d6 {
+            return (c, Feature::Vertex(2));
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0. && d2 >= 0. && d6 <= 0. {
+            return (a + ac * (d2 / (d2 - d6)), Feature::Edge(2));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+            let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+            return (b + (c - b) * w, Feature::Edge(1));
+        }
+
+        let denom = 1. / (va + vb + vc);
+        (a + ab * (vb * denom) + ac * (vc * denom), Feature::Face)
+    }
+
+    fn pseudo_normal(&self, feature: Feature) -> Vec3 {
+        match feature {
+            Feature::Vertex(i) => self.vertex_normals[i],
+            Feature::Edge(i) => self.edge_normals[i],
+            Feature::Face => self.normal,
+        }
+    }
+}
+
+/// Closed triangle mesh evaluated as an exact signed distance field. The
+/// surface must be watertight and consistently wound counter-clockwise seen
+/// from outside for the sign to be right.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub triangles: Vec<MeshTriangle>,
+    pub bounds: Aabb,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    /// Loads an `.obj` or `.stl` file.
+    pub fn load(path: &str) -> Result<Mesh, AppError> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let raw = match extension.as_deref() {
+            Some("obj") => import::load_obj(path)?,
+            Some("stl") => import::load_stl(path)?,
+            _ => {
+                return Err(AppError::ErrorString(format!(
+                    "{}: unsupported mesh format",
+                    path
+                )))
+            }
+        };
+        let mesh = Mesh::new(raw);
+        if mesh.triangles.is_empty() {
+            return Err(AppError::ErrorString(format!("{}: no triangles", path)));
+        }
+        Ok(mesh)
+    }
+
+    pub fn new(raw: RawMesh) -> Mesh {
+        let RawMesh { vertices, faces } = raw;
+        // Degenerate triangles have no normal to contribute.
+        let faces: Vec<[u32; 3]> = faces
+            .into_iter()
+            .filter(|f| {
+                let [a, b, c] = f.map(|i| vertices[i as usize]);
+                (b - a).cross(c - a).length_squared() > 0.
+            })
+            .collect();
+        let normals: Vec<Vec3> = faces
+            .iter()
+            .map(|f| {
+                let [a, b, c] = f.map(|i| vertices[i as usize]);
+                (b - a).cross(c - a).normalize()
+            })
+            .collect();
+
+        let mut vertex_normals = vec![Vec3::ZERO; vertices.len()];
+        let mut edge_normals: HashMap<(u32, u32), Vec3> = HashMap::new();
+        let edge = |a: u32, b: u32| (a.min(b), a.max(b));
+        for (f, n) in faces.iter().zip(&normals) {
+            for i in 0..3 {
+                let (v, next, prev) = (f[i], f[(i + 1) % 3], f[(i + 2) % 3]);
+                let e1 = vertices[next as usize] - vertices[v as usize];
+                let e2 = vertices[prev as usize] - vertices[v as usize];
+                vertex_normals[v as usize] += e1.angle_between(e2) * *n;
+                *edge_normals.entry(edge(v, next)).or_default() += *n;
+            }
+        }
+
+        let triangles: Vec<MeshTriangle> = faces
+            .iter()
+            .zip(&normals)
+            .map(|(f, n)| MeshTriangle {
+                vertices: f.map(|i| vertices[i as usize]),
+                normal: *n,
+                vertex_normals: f.map(|i| vertex_normals[i as usize].normalize_or_zero()),
+                edge_normals: [0, 1, 2]
+                    .map(|i| edge_normals[&edge(f[i], f[(i + 1) % 3])].normalize_or_zero()),
+            })
+            .collect();
+
+        let boxes: Vec<Aabb> = triangles.iter().map(|t| t.bounds()).collect();
+        let bvh = Bvh::build(&boxes);
+        let bounds = bvh.nodes[0].bounds;
+        Mesh {
+            triangles,
+            bounds,
+            bvh,
+        }
+    }
+
+    /// Exact distance to the closest triangle, negative inside.
+    pub fn signed_distance(&self, p: Vec3) -> f32 {
+        let outside = self.bounds.distance(p);
+        if outside > self.bounds.size().length() * FAR_FIELD {
+            return outside;
+        }
+
+        let nearest = self.bvh.nearest(p, |i| {
+            (self.triangles[i].closest_point(p).0 - p).length_squared()
+        });
+        let Some((i, d2)) = nearest else {
+            return outside;
+        };
+        let triangle = &self.triangles[i];
+        let (q, feature) = triangle.closest_point(p);
+        let d = d2.sqrt();
+        if (p - q).dot(triangle.pseudo_normal(feature)) < 0. {
+            -d
+        } else {
+            d
+        }
+    }
+
+    /// Samples the signed distance into a grid around the mesh, with
+    /// `resolution` samples along its longest side.
+    pub fn bake(&self, resolution: u32) -> DistanceGrid {
+        let size = self.bounds.size();
+        let margin = size.max_element() * 0.05;
+        let bounds = self.bounds.expand(margin);
+        DistanceGrid::bake(bounds.min, bounds.max, resolution, |p| {
+            self.signed_distance(p)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_marching::sdfs::box_sdf;
+    use glam::vec3;
+
+    /// Cube from -1 to 1 with quads, a `v/vt/vn` face and negative indices.
+    static CUBE_OBJ: &str = "\
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 4 3 2
+f 5/1/1 6/1/1 7/1/1 8/1/1
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f -7 -6 -2 -3
+";
+
+    fn write_temp(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("mesh_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn cube() -> Mesh {
+        Mesh::load(&write_temp("cube.obj", CUBE_OBJ.as_bytes())).unwrap()
+    }
+
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..600).map(|i| {
+            vec3(
+                (i % 9) as f32 * 0.41 - 1.63,
+                (i * 7 % 11) as f32 * 0.33 - 1.67,
+                (i * 13 % 10) as f32 * 0.37 - 1.61,
+            )
+        })
+    }
+
+    #[test]
+    fn obj_faces_are_triangulated() {
+        let raw = import::load_obj(&write_temp("quads.obj", CUBE_OBJ.as_bytes())).unwrap();
+        assert_eq!(raw.vertices.len(), 8);
+        assert_eq!(raw.faces.len(), 12);
+        assert_eq!(raw.faces[10], [1, 2, 6]);
+    }
+
+    #[test]
+    fn obj_rejects_bad_indices() {
+        let path = write_temp("bad.obj", b"v 0 0 0\nv 1 0 0\nf 1 2 3\n");
+        let Err(AppError::ErrorString(e)) = import::load_obj(&path) else {
+            panic!("face with a missing vertex was accepted");
+        };
+        assert!(e.ends_with(":3: invalid face index"), "{}", e);
+    }
+
+    #[test]
+    fn stl_vertices_are_welded() {
+        let mesh = cube();
+        let triangles: Vec<[Vec3; 3]> = mesh.triangles.iter().map(|t| t.vertices).collect();
+
+        let mut ascii = String::from("solid cube\n");
+        let mut binary = vec![0u8; 80];
+        binary.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for t in &triangles {
+            ascii.push_str("facet normal 0 0 0\nouter loop\n");
+            binary.extend_from_slice(&[0; 12]);
+            for v in t {
+                ascii.push_str(&format!("vertex {} {} {}\n", v.x, v.y, v.z));
+                v.to_array()
+                    .iter()
+                    .for_each(|c| binary.extend_from_slice(&c.to_le_bytes()));
+            }
+            ascii.push_str("endloop\nendfacet\n");
+            binary.extend_from_slice(&[0; 2]);
+        }
+        ascii.push_str("endsolid cube\n");
+
+        for (name, bytes) in [("ascii.stl", ascii.as_bytes()), ("binary.stl", &binary)] {
+            let raw = import::load_stl(&write_temp(name, bytes)).unwrap();
+            assert_eq!(raw.vertices.len(), 8, "{}", name);
+            assert_eq!(raw.faces.len(), 12, "{}", name);
+        }
+    }
+
+    #[test]
+    fn cube_matches_box_sdf() {
+        let mesh = cube();
+        // Points near the edges and corners take their sign from the
+        // pseudo-normals of those features.
+        let near_features = [
+            vec3(1.1, 1.1, 0.3),
+            vec3(1.05, 1.1, 1.2),
+            vec3(0.9, 0.95, 0.97),
+            vec3(-0.99, 0.2, -0.98),
+        ];
+        for p in points().chain(near_features) {
+            let expected = box_sdf(p, Vec3::ONE, 0.);
+            let d = mesh.signed_distance(p);
+            assert!((d - expected).abs() < 1e-4, "{p}: {d} expected {expected}");
+        }
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mesh = cube();
+        let distance2 =
+            |p: Vec3, i: usize| (mesh.triangles[i].closest_point(p).0 - p).length_squared();
+        for p in points() {
+            let (_, d2) = mesh.bvh.nearest(p, |i| distance2(p, i)).unwrap();
+            let brute = (0..mesh.triangles.len())
+                .map(|i| distance2(p, i))
+                .fold(f32::MAX, f32::min);
+            assert_eq!(d2, brute, "{p}");
+        }
+    }
+
+    #[test]
+    fn degenerate_faces_are_dropped() {
+        let path = write_temp("flat.obj", b"v 0 0 0\nv 1 0 0\nv 2 0 0\nf 1 2 3\n");
+        let Err(AppError::ErrorString(e)) = Mesh::load(&path) else {
+            panic!("mesh without area was accepted");
+        };
+        assert!(e.ends_with(": no triangles"), "{}", e);
+    }
+}
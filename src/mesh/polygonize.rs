@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use glam::{uvec3, Mat3, UVec3, Vec3};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::ray::Ray;
+use crate::ray_marching::RayMarching;
+use crate::scene::Scene;
+
+use super::bvh::Aabb;
+use super::export::TriangleMesh;
+
+/// Weight pulling dual contouring vertices towards the mean of the edge
+/// crossings, which keeps flat regions well conditioned.
+static QEF_BIAS: f32 = 0.05;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Contouring {
+    /// One vertex per crossed grid edge. Smooth, but rounds sharp features.
+    MarchingCubes,
+    /// One vertex per crossed cell, placed on the intersection of the tangent
+    /// planes, which keeps edges and corners sharp.
+    DualContouring,
+}
+
+/// Extracts the zero level set of a scene's SDF inside a box.
+#[derive(Debug, Clone)]
+pub struct Polygonizer {
+    pub bounds: Aabb,
+    /// Number of cells along the longest side of the box.
+    pub resolution: u32,
+    pub method: Contouring,
+}
+
+/// Corners of a cell, bit 0 is x, bit 1 y and bit 2 z.
+fn corner(c: usize) -> UVec3 {
+    uvec3(c as u32 & 1, (c as u32 >> 1) & 1, (c as u32 >> 2) & 1)
+}
+
+/// Cell edges as pairs of corners, the first one with the lower coordinate.
+static EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// Corners of each face in cyclic order.
+static FACES: [[usize; 4]; 6] = [
+    [0, 2, 6, 4],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 3, 7, 6],
+    [0, 1, 3, 2],
+    [4, 5, 7, 6],
+];
+
+fn edge_between(a: usize, b: usize) -> usize {
+    EDGES
+        .iter()
+        .position(|&(c0, c1)| (c0, c1) == (a.min(b), a.max(b)))
+        .unwrap()
+}
+
+/// For each of the 256 inside/outside configurations of a cell, the loops of
+/// crossed edges bounding the surface inside it. Built by joining the
+/// crossings on each face; on faces with four crossings the inside corners
+/// are cut off, a choice that only depends on the face so neighbouring cells
+/// agree.
+fn cell_loops() -> &'static Vec<Vec<Vec<usize>>> {
+    static LOOPS: OnceLock<Vec<Vec<Vec<usize>>>> = OnceLock::new();
+    LOOPS.get_or_init(|| {
+        (0..256usize)
+            .map(|case| {
+                let inside = |c: usize| case & (1 << c) != 0;
+                let mut links: Vec<Vec<usize>> = vec![vec![]; 12];
+                for face in &FACES {
+                    let crossed: Vec<usize> = (0..4)
+                        .filter(|&k| inside(face[k]) != inside(face[(k + 1) % 4]))
+                        .map(|k| edge_between(face[k], face[(k + 1) % 4]))
+                        .collect();
+                    let mut link = |a: usize, b: usize| {
+                        links[a].push(b);
+                        links[b].push(a);
+                    };
+                    match crossed.len() {
+                        2 => link(crossed[0], crossed[1]),
+                        4 => {
+                            for k in (0..4).filter(|&k| inside(face[k])) {
+                                let prev = edge_between(face[(k + 3) % 4], face[k]);
+                                let next = edge_between(face[k], face[(k + 1) % 4]);
+                                link(prev, next);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                let mut loops = vec![];
+                let mut visited = [false; 12];
+                for start in 0..12 {
+                    if visited[start] || links[start].is_empty() {
+                        continue;
+                    }
+                    let mut polygon = vec![start];
+                    visited[start] = true;
+                    let mut current = start;
+                    while let Some(&next) = links[current].iter().find(|e| !visited[**e]) {
+                        visited[next] = true;
+                        polygon.push(next);
+                        current = next;
+                    }
+                    // Crossed edges point from their inside to their outside
+                    // corner. Winds the loop of edge midpoints around that
+                    // direction.
+                    let midpoint = |e: usize| {
+                        let (c0, c1) = EDGES[e];
+                        (corner(c0) + corner(c1)).as_vec3() * 0.5
+                    };
+                    let outward: Vec3 = polygon
+                        .iter()
+                        .map(|&e| {
+                            let (c0, c1) = EDGES[e];
+                            let dir = (corner(c1) - corner(c0)).as_vec3();
+                            if inside(c0) {
+                                dir
+                            } else {
+                                -dir
+                            }
+                        })
+                        .sum();
+                    let area: Vec3 = (0..polygon.len())
+                        .map(|i| {
+                            let next = polygon[(i + 1) % polygon.len()];
+                            midpoint(polygon[i]).cross(midpoint(next))
+                        })
+                        .sum();
+                    if area.dot(outward) < 0. {
+                        polygon.reverse();
+                    }
+                    loops.push(polygon);
+                }
+                loops
+            })
+            .collect()
+    })
+}
+
+/// Signed distances at the grid points.
+struct Samples {
+    min: Vec3,
+    cell: f32,
+    /// Number of points along each axis.
+    size: UVec3,
+    values: Vec<f32>,
+}
+
+impl Samples {
+    fn index(&self, p: UVec3) -> usize {
+        (p.x + self.size.x * (p.y + self.size.y * p.z)) as usize
+    }
+
+    fn value(&self, p: UVec3) -> f32 {
+        self.values[self.index(p)]
+    }
+
+    fn position(&self, p: UVec3) -> Vec3 {
+        self.min + p.as_vec3() * self.cell
+    }
+
+    /// Zero crossing along the edge from grid point `p` along `axis`.
+    fn crossing(&self, p: UVec3, axis: usize) -> Option<Vec3> {
+        let q = p + UVec3::AXES[axis];
+        let (a, b) = (self.value(p), self.value(q));
+        if (a < 0.) == (b < 0.) {
+            return None;
+        }
+        let t = a / (a - b);
+        Some(self.position(p).lerp(self.position(q), t))
+    }
+}
+
+fn distance(rm: &RayMarching, p: Vec3) -> f32 {
+    rm.distance(
+        &Ray {
+            origin: p,
+            direction: Vec3::Z,
+        },
+        0.,
+    )
+    .dist
+}
+
+impl Polygonizer {
+    pub fn new(bounds: Aabb, resolution: u32) -> Polygonizer {
+        Polygonizer {
+            bounds,
+            resolution,
+            method: Contouring::MarchingCubes,
+        }
+    }
+
+    pub fn polygonize(&self, scene: &Scene) -> TriangleMesh {
+        let samples = self.sample(scene);
+        let mut mesh = match self.method {
+            Contouring::MarchingCubes => Self::marching_cubes(&samples),
+            Contouring::DualContouring => Self::dual_contouring(&samples, scene),
+        };
+        mesh.shade(scene);
+        mesh
+    }
+
+    fn sample(&self, scene: &Scene) -> Samples {
+        let rm = RayMarching { scene };
+        let extent = self.bounds.size();
+        let cell = extent.max_element() / self.resolution.max(1) as f32;
+        let size = (extent / cell).ceil().as_uvec3().max(UVec3::ONE) + 1;
+        let min = self.bounds.min;
+
+        let count = (size.x * size.y * size.z) as usize;
+        let values = (0..count)
+            .into_par_iter()
+            .map(|i| {
+                let i = i as u32;
+                let p = uvec3(i % size.x, i / size.x % size.y, i / (size.x * size.y));
+                distance(&rm, min + p.as_vec3() * cell)
+            })
+            .collect();
+
+        Samples {
+            min,
+            cell,
+            size,
+            values,
+        }
+    }
+
+    fn marching_cubes(samples: &Samples) -> TriangleMesh {
+        let loops = cell_loops();
+        let mut mesh = TriangleMesh::default();
+        // Vertices are shared through the grid edge they lie on.
+        let mut vertices: HashMap<(usize, usize), u32> = HashMap::new();
+
+        let cells = samples.size - 1;
+        for z in 0..cells.z {
+            for y in 0..cells.y {
+                for x in 0..cells.x {
+                    let base = uvec3(x, y, z);
+                    let case = (0..8).fold(0, |case, c| {
+                        let inside = samples.value(base + corner(c)) < 0.;
+                        case | ((inside as usize) << c)
+                    });
+                    if case == 0 || case == 255 {
+                        continue;
+                    }
+
+                    for polygon in &loops[case] {
+                        let ids: Vec<u32> = polygon
+                            .iter()
+                            .map(|&e| {
+                                let (c0, c1) = EDGES[e];
+                                let p = base + corner(c0);
+                                let axis = (c0 ^ c1).trailing_zeros() as usize;
+                                let key = (samples.index(p), axis);
+                                *vertices.entry(key).or_insert_with(|| {
+                                    let v = samples.crossing(p, axis).unwrap();
+                                    mesh.positions.push(v);
+                                    mesh.positions.len() as u32 - 1
+                                })
+                            })
+                            .collect();
+                        for i in 1..ids.len() - 1 {
+                            mesh.faces.push([ids[0], ids[i], ids[i + 1]]);
+                        }
+                    }
+                }
+            }
+        }
+        mesh
+    }
+
+    fn dual_contouring(samples: &Samples, scene: &Scene) -> TriangleMesh {
+        let rm = RayMarching { scene };
+        let mut mesh = TriangleMesh::default();
+        let mut vertices: HashMap<UVec3, u32> = HashMap::new();
+        let cells = samples.size - 1;
+
+        // Vertex of a cell, minimizing the distance to the tangent planes at
+        // the crossings of its edges.
+        let mut cell_vertex = |cell: UVec3, mesh: &mut TriangleMesh| -> u32 {
+            *vertices.entry(cell).or_insert_with(|| {
+                let mut ata = Mat3::ZERO;
+                let mut atb = Vec3::ZERO;
+                let mut mass = Vec3::ZERO;
+                let mut count = 0.;
+                for &(c0, c1) in &EDGES {
+                    let p = cell + corner(c0);
+                    let axis = (c0 ^ c1).trailing_zeros() as usize;
+                    if let Some(x) = samples.crossing(p, axis) {
+                        let n = rm.normal(x);
+                        ata += Mat3::from_cols(n * n.x, n * n.y, n * n.z);
+                        atb += n * n.dot(x);
+                        mass += x;
+                        count += 1.;
+                    }
+                }
+                let mass = mass / count;
+                let ata = ata + Mat3::from_diagonal(Vec3::splat(QEF_BIAS));
+                let atb = atb + mass * QEF_BIAS;
+                let v = if ata.determinant().abs() > 1e-6 {
+                    ata.inverse() * atb
+                } else {
+                    mass
+                };
+                // Vertices leaving their cell fold the surface over itself.
+                let lo = samples.position(cell);
+                let v = v.clamp(lo, lo + samples.cell);
+                mesh.positions.push(v);
+                mesh.positions.len() as u32 - 1
+            })
+        };
+
+        // Each crossed edge inside the grid gets a quad joining the vertices
+        // of the four cells around it.
+        for z in 0..samples.size.z {
+            for y in 0..samples.size.y {
+                for x in 0..samples.size.x {
+                    let p = uvec3(x, y, z);
+                    for axis in 0..3 {
+                        if p[axis] + 1 >= samples.size[axis] || samples.crossing(p, axis).is_none()
+                        {
+                            continue;
+                        }
+                        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                        if p[u] == 0 || p[v] == 0 || p[u] >= cells[u] || p[v] >= cells[v] {
+                            continue;
+                        }
+                        // Counter-clockwise around `axis`, facing +axis.
+                        let mut quad = [(1, 1), (0, 1), (0, 0), (1, 0)].map(|(du, dv)| {
+                            let mut c = p;
+                            c[u] -= du;
+                            c[v] -= dv;
+                            cell_vertex(c, &mut mesh)
+                        });
+                        if samples.value(p) >= 0. {
+                            quad.reverse();
+                        }
+                        mesh.faces.push([quad[0], quad[1], quad[2]]);
+                        mesh.faces.push([quad[0], quad[2], quad[3]]);
+                    }
+                }
+            }
+        }
+        mesh
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+
+use fontdue_sdl2::fontdue::layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle};
+use fontdue_sdl2::fontdue::{Font, FontSettings};
+use fontdue_sdl2::FontTexture;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+use crate::camera::Camera;
+use crate::renderer::Renderer;
+use crate::utils::errors::AppError;
+
+static FONT_SIZE: f32 = 15.;
+static MARGIN: i32 = 8;
+
+/// On-screen text panel showing the renderer statistics.
+pub struct Overlay<'r> {
+    pub visible: bool,
+    fonts: [Font; 1],
+    font_texture: FontTexture<'r>,
+    layout: Layout<Color>,
+}
+
+impl<'r> Overlay<'r> {
+    pub fn new(
+        texture_creator: &'r TextureCreator<WindowContext>,
+        font_path: &str,
+    ) -> Result<Overlay<'r>, AppError> {
+        let bytes = fs::read(font_path)?;
+        let font = Font::from_bytes(bytes, FontSettings::default())
+            .map_err(|e| AppError::ErrorString(e.to_string()))?;
+
+        Ok(Overlay {
+            visible: false,
+            fonts: [font],
+            font_texture: FontTexture::new(texture_creator)?,
+            layout: Layout::new(CoordinateSystem::PositiveYDown),
+        })
+    }
+
+    pub fn lines(renderer: &Renderer, camera: &Camera) -> Vec<String> {
+        let stats = renderer.stats();
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.;
+        vec![
+            format!(
+                "mode {:?}, {} samples, temporal {}, denoise {}",
+                renderer.mode,
+                renderer.samples(),
+                renderer.temporal,
+                renderer.denoise
+            ),
+            format!(
+                "frame {:.1} ms: restart {:.1}, tiles {:.1}, denoise {:.1}, upload {:.1}",
+                ms(stats.frame_time),
+                ms(stats.restart_time),
+                ms(stats.tiles_time),
+                ms(stats.denoise_time),
+                ms(stats.upload_time)
+            ),
+            format!(
+                "tiles {} done, {} pending, {:.2} ms avg, {:.2} ms max",
+                stats.tiles,
+                stats.pending_tiles,
+                ms(stats.average_tile_time),
+                ms(stats.max_tile_time)
+            ),
+            format!(
+                "rays {:.2} M/s, steps {:.1} avg, {} max, hits {:.0}%",
+                stats.rays_per_second / 1e6,
+                stats.march.average_steps(),
+                stats.march.max_steps,
+                stats.march.hit_ratio() * 100.
+            ),
+            format!(
+                "camera {:?} ({:.2}, {:.2}, {:.2}) looking ({:.2}, {:.2}, {:.2})",
+                camera.controller,
+                camera.position.x,
+                camera.position.y,
+                camera.position.z,
+                camera.ww.x,
+                camera.ww.y,
+                camera.ww.z
+            ),
+        ]
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, lines: &[String]) -> Result<(), String> {
+        if !self.visible {
+            return Ok(());
+        }
+        self.draw_lines(canvas, lines, Color::RGB(0xFF, 0xFF, 0xFF))
+    }
+
+    /// Shows an error message, whether or not the overlay is visible.
+    pub fn draw_error(&mut self, canvas: &mut Canvas<Window>, message: &str) -> Result<(), String> {
+        let lines: Vec<String> = message.lines().map(String::from).collect();
+        self.draw_lines(canvas, &lines, Color::RGB(0xFF, 0x60, 0x60))
+    }
+
+    fn draw_lines(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        lines: &[String],
+        color: Color,
+    ) -> Result<(), String> {
+        self.layout.reset(&LayoutSettings {
+            x: MARGIN as f32,
+            y: MARGIN as f32,
+            ..Default::default()
+        });
+        for line in lines {
+            let text = format!("{}\n", line);
+            let style = TextStyle::with_user_data(&text, FONT_SIZE, 0, color);
+            self.layout.append(&self.fonts, &style);
+        }
+
+        let width = self
+            .layout
+            .glyphs()
+            .iter()
+            .map(|g| g.x as i32 + g.width as i32)
+            .max()
+            .unwrap_or(0);
+        let height = self.layout.height() as i32;
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        canvas.fill_rect(Rect::new(
+            0,
+            0,
+            (width + MARGIN) as u32,
+            (height + 2 * MARGIN) as u32,
+        ))?;
+
+        self.font_texture
+            .draw_text(canvas, &self.fonts, self.layout.glyphs())
+    }
+}
@@ -3,7 +3,7 @@ use rand::{rngs::ThreadRng, Rng};
 
 use crate::light::{Light, LightSource};
 
-use crate::utils::materials::Material;
+use crate::utils::materials::{Material, Pbr};
 use crate::utils::math;
 
 pub static EPSILON: f32 = 0.0001_f32;
@@ -95,6 +95,12 @@ impl Ray {
         ambience + diffuse + specular
     }
 
+    pub fn cook_torrance(&self, normal: &Vec3, point: &Vec3, light: &Light, pbr: &Pbr) -> Vec3 {
+        pbr.evaluate(*normal, -self.direction, -light.direction(*point))
+            * light.albedo()
+            * light.intensity()
+    }
+
     pub fn reflection_ray(
         &self,
         hit: RayHit,
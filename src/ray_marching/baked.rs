@@ -0,0 +1,295 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Write as _;
+use std::fs;
+use std::hash::Hasher;
+use std::path::Path;
+
+use glam::{UVec3, Vec3};
+
+use crate::mesh::bvh::Aabb;
+use crate::scene::Scene;
+use crate::utils::errors::AppError;
+
+use super::distance_grid::{Brick, BrickGrid, DistanceGrid};
+use super::sdf_tree::SdfNode;
+
+static CACHE_DIRECTORY: &str = "./cache";
+static CACHE_MAGIC: &[u8; 4] = b"SDFV";
+static CACHE_VERSION: u32 = 1;
+static BRICK_SIZE: u32 = 8;
+/// Width of the band around the surface, in cell diagonals, where the exact
+/// SDF is evaluated instead of the grid.
+static BAND_CELLS: f32 = 2.;
+
+/// How a subtree is baked.
+#[derive(Debug, Copy, Clone)]
+pub struct BakeSettings {
+    /// Number of cells along the longest side of the bounds.
+    pub resolution: u32,
+    /// Only keep the samples of the bricks near the surface.
+    pub sparse: bool,
+    /// Baked region, the bounds of the subtree when not given.
+    pub bounds: Option<Aabb>,
+    /// Reuse volumes baked earlier from `./cache`.
+    pub cache: bool,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 64,
+            sparse: false,
+            bounds: None,
+            cache: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BakedGrid {
+    Dense(DistanceGrid),
+    Sparse(BrickGrid),
+}
+
+/// Distance field of a subtree sampled on a grid.
+#[derive(Debug, Clone)]
+pub struct BakedVolume {
+    pub grid: BakedGrid,
+    /// Below this distance the subtree is evaluated exactly.
+    pub band: f32,
+    /// Material reported away from the surface, where only the distance is
+    /// used.
+    pub material: usize,
+}
+
+/// Feeds formatted text into a hasher, to key the cache on a node's debug
+/// representation without building the string.
+struct HashWriter(DefaultHasher);
+
+impl std::fmt::Write for HashWriter {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl BakedVolume {
+    /// Bakes `child`, or loads it from the cache when the same subtree was
+    /// baked with the same settings before.
+    pub fn bake(
+        child: &SdfNode,
+        scene: &Scene,
+        settings: &BakeSettings,
+    ) -> Result<BakedVolume, AppError> {
+        let cell_bounds = settings
+            .bounds
+            .or_else(|| child.bounds(scene))
+            .ok_or_else(|| {
+                AppError::ErrorString("cannot bake an unbounded subtree without bounds".to_string())
+            })?;
+        let size = cell_bounds.size().max_element();
+        let cell = size / settings.resolution.max(1) as f32;
+        let band = BAND_CELLS * cell * 3f32.sqrt();
+        let bounds = cell_bounds.expand(band);
+
+        // Nodes below may read transforms and parameters of the scene.
+        let mut hasher = HashWriter(DefaultHasher::new());
+        write!(
+            hasher,
+            "{:?} {:?} {:?} {:?} {:?} {}",
+            child, scene.transforms, scene.params, bounds.min, bounds.max, settings.resolution
+        )
+        .map_err(|e| AppError::ErrorString(e.to_string()))?;
+        let path = format!(
+            "{}/{:016x}{}.sdf",
+            CACHE_DIRECTORY,
+            hasher.0.finish(),
+            if settings.sparse { "s" } else { "" }
+        );
+
+        // An unreadable cache entry is baked again and overwritten.
+        if settings.cache && Path::new(&path).exists() {
+            if let Ok(volume) = BakedVolume::load(&path) {
+                return Ok(volume);
+            }
+        }
+
+        let grid = DistanceGrid::bake(bounds.min, bounds.max, settings.resolution + 1, |p| {
+            child.distance(scene, p).0
+        });
+        let center = bounds.center();
+        let material = child.distance(scene, center).1;
+        let grid = if settings.sparse {
+            BakedGrid::Sparse(BrickGrid::from_grid(&grid, BRICK_SIZE, band))
+        } else {
+            BakedGrid::Dense(grid)
+        };
+        let volume = BakedVolume {
+            grid,
+            band,
+            material,
+        };
+
+        if settings.cache {
+            fs::create_dir_all(CACHE_DIRECTORY)?;
+            volume.save(&path)?;
+        }
+        Ok(volume)
+    }
+
+    pub fn sample(&self, p: Vec3) -> f32 {
+        match &self.grid {
+            BakedGrid::Dense(grid) => grid.sample(p),
+            BakedGrid::Sparse(grid) => grid.sample(p),
+        }
+    }
+
+    /// Writes the volume as little endian binary: a header with the grid
+    /// placement, then the dense samples or the bricks.
+    pub fn save(&self, path: &str) -> Result<(), AppError> {
+        let mut out = CACHE_MAGIC.to_vec();
+        let u32s = |out: &mut Vec<u8>, values: &[u32]| {
+            values
+                .iter()
+                .for_each(|v| out.extend_from_slice(&v.to_le_bytes()))
+        };
+        let f32s = |out: &mut Vec<u8>, values: &[f32]| {
+            values
+                .iter()
+                .for_each(|v| out.extend_from_slice(&v.to_le_bytes()))
+        };
+
+        let (min, max, resolution) = match &self.grid {
+            BakedGrid::Dense(g) => (g.min, g.max, g.resolution),
+            BakedGrid::Sparse(g) => (g.min, g.max, g.resolution),
+        };
+        let sparse = matches!(self.grid, BakedGrid::Sparse(_));
+        u32s(
+            &mut out,
+            &[CACHE_VERSION, sparse as u32, self.material as u32],
+        );
+        f32s(&mut out, &[self.band]);
+        f32s(&mut out, &min.to_array());
+        f32s(&mut out, &max.to_array());
+        u32s(&mut out, &resolution.to_array());
+
+        match &self.grid {
+            BakedGrid::Dense(g) => f32s(&mut out, &g.values),
+            BakedGrid::Sparse(g) => {
+                u32s(&mut out, &[g.brick_size]);
+                for brick in &g.bricks {
+                    match brick {
+                        Brick::Constant(v) => {
+                            out.push(0);
+                            f32s(&mut out, &[*v]);
+                        }
+                        Brick::Dense(values) => {
+                            out.push(1);
+                            f32s(&mut out, values);
+                        }
+                    }
+                }
+            }
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<BakedVolume, AppError> {
+        let bytes = fs::read(path)?;
+        if bytes.get(..4) != Some(CACHE_MAGIC) {
+            return Err(invalid_volume(path));
+        }
+        let mut r = Reader {
+            bytes: &bytes,
+            offset: 4,
+            path,
+        };
+        if r.u32()? != CACHE_VERSION {
+            return Err(invalid_volume(path));
+        }
+        let sparse = r.u32()? != 0;
+        let material = r.u32()? as usize;
+        let band = r.f32()?;
+        let min = Vec3::new(r.f32()?, r.f32()?, r.f32()?);
+        let max = Vec3::new(r.f32()?, r.f32()?, r.f32()?);
+        let resolution = UVec3::new(r.u32()?, r.u32()?, r.u32()?);
+        if resolution.cmplt(UVec3::splat(2)).any() {
+            return Err(invalid_volume(path));
+        }
+
+        let grid = if sparse {
+            let brick_size = r.u32()?.max(1);
+            let bricks_count = (resolution - 1 + brick_size - 1) / brick_size;
+            let side = (brick_size + 1) as usize;
+            let count = (bricks_count.x * bricks_count.y * bricks_count.z) as usize;
+            let bricks = (0..count)
+                .map(|_| match r.u8()? {
+                    0 => Ok(Brick::Constant(r.f32()?)),
+                    _ => Ok(Brick::Dense(r.f32s(side * side * side)?)),
+                })
+                .collect::<Result<_, AppError>>()?;
+            BakedGrid::Sparse(BrickGrid {
+                min,
+                max,
+                resolution,
+                brick_size,
+                bricks_count,
+                bricks,
+            })
+        } else {
+            let count = (resolution.x * resolution.y * resolution.z) as usize;
+            BakedGrid::Dense(DistanceGrid {
+                min,
+                max,
+                resolution,
+                values: r.f32s(count)?,
+            })
+        };
+
+        Ok(BakedVolume {
+            grid,
+            band,
+            material,
+        })
+    }
+}
+
+fn invalid_volume(path: &str) -> AppError {
+    AppError::ErrorString(format!("{}: invalid baked volume", path))
+}
+
+/// Little endian values read from the front of a cache file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    path: &'a str,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], AppError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + n)
+            .ok_or_else(|| invalid_volume(self.path))?;
+        self.offset += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, AppError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, AppError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, AppError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn f32s(&mut self, count: usize) -> Result<Vec<f32>, AppError> {
+        (0..count).map(|_| self.f32()).collect()
+    }
+}
@@ -0,0 +1,271 @@
+use glam::{uvec3, UVec3, Vec3};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Bound of the distance at `outside` from the grid point whose distance is
+/// `d`: at least the distance to the grid box, and at least `d` minus the way
+/// travelled.
+fn outside_bound(d: f32, outside: f32) -> f32 {
+    if outside > 0. {
+        outside.max(d - outside)
+    } else {
+        d
+    }
+}
+
+/// Signed distances sampled on a regular grid and read back with trilinear
+/// interpolation. Outside the grid the result stays a lower bound of the
+/// distance, so marching from afar is safe as long as the surface lies within
+/// the grid.
+#[derive(Debug, Clone)]
+pub struct DistanceGrid {
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Number of samples along each axis, corners included.
+    pub resolution: UVec3,
+    pub values: Vec<f32>,
+}
+
+impl DistanceGrid {
+    /// Evaluates `f` in parallel at every sample, with `resolution` samples
+    /// along the longest side of the box and as many cells of the same size on
+    /// the others.
+    pub fn bake(
+        min: Vec3,
+        max: Vec3,
+        resolution: u32,
+        f: impl Fn(Vec3) -> f32 + Sync,
+    ) -> DistanceGrid {
+        let size = max - min;
+        let cell = size.max_element() / (resolution.max(2) - 1) as f32;
+        let resolution = (size / cell).ceil().as_uvec3().max(UVec3::ONE) + 1;
+        // Grow the box to a whole number of cells.
+        let max = min + (resolution - 1).as_vec3() * cell;
+
+        let count = (resolution.x * resolution.y * resolution.z) as usize;
+        let values = (0..count)
+            .into_par_iter()
+            .map(|i| {
+                let i = i as u32;
+                let x = i % resolution.x;
+                let y = i / resolution.x % resolution.y;
+                let z = i / (resolution.x * resolution.y);
+                f(min + uvec3(x, y, z).as_vec3() * cell)
+            })
+            .collect();
+
+        DistanceGrid {
+            min,
+            max,
+            resolution,
+            values,
+        }
+    }
+
+    pub fn cell_size(&self) -> Vec3 {
+        (self.max - self.min) / (self.resolution - 1).as_vec3()
+    }
+
+    fn value(&self, x: u32, y: u32, z: u32) -> f32 {
+        let r = self.resolution;
+        self.values[(x + r.x * (y + r.y * z)) as usize]
+    }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        p.cmpge(self.min).all() && p.cmple(self.max).all()
+    }
+
+    pub fn sample(&self, p: Vec3) -> f32 {
+        let q = p.clamp(self.min, self.max);
+        let outside = (p - q).length();
+
+        let g = (q - self.min) / self.cell_size();
+        let i = g.floor().as_uvec3().min(self.resolution - 2);
+        let t = g - i.as_vec3();
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let c = |dx, dy, dz| self.value(i.x + dx, i.y + dy, i.z + dz);
+        let x00 = lerp(c(0, 0, 0), c(1, 0, 0), t.x);
+        let x10 = lerp(c(0, 1, 0), c(1, 1, 0), t.x);
+        let x01 = lerp(c(0, 0, 1), c(1, 0, 1), t.x);
+        let x11 = lerp(c(0, 1, 1), c(1, 1, 1), t.x);
+        let y0 = lerp(x00, x10, t.y);
+        let y1 = lerp(x01, x11, t.y);
+        outside_bound(lerp(y0, y1, t.z), outside)
+    }
+}
+
+/// Block of `brick_size` cells per side of a `BrickGrid`.
+#[derive(Debug, Clone)]
+pub enum Brick {
+    /// Brick away from the surface, with a value no larger than the distance
+    /// anywhere inside it.
+    Constant(f32),
+    /// `(brick_size + 1)^3` samples, sharing their borders with the
+    /// neighbours.
+    Dense(Vec<f32>),
+}
+
+/// Sparse grid keeping samples only in the bricks near the surface.
+#[derive(Debug, Clone)]
+pub struct BrickGrid {
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Number of samples along each axis of the full grid.
+    pub resolution: UVec3,
+    pub brick_size: u32,
+    /// Number of bricks along each axis.
+    pub bricks_count: UVec3,
+    pub bricks: Vec<Brick>,
+}
+
+impl BrickGrid {
+    /// Splits `grid` into bricks, dropping the samples of the ones that have
+    /// no value within `band` of the surface.
+    pub fn from_grid(grid: &DistanceGrid, brick_size: u32, band: f32) -> BrickGrid {
+        let cells = grid.resolution - 1;
+        let bricks_count = (cells + brick_size - 1) / brick_size;
+        let half_diagonal = grid.cell_size().length() * 0.5;
+        let side = brick_size + 1;
+
+        let mut bricks = vec![];
+        for bz in 0..bricks_count.z {
+            for by in 0..bricks_count.y {
+                for bx in 0..bricks_count.x {
+                    let base = uvec3(bx, by, bz) * brick_size;
+                    let mut values = Vec::with_capacity((side * side * side) as usize);
+                    for z in 0..side {
+                        for y in 0..side {
+                            for x in 0..side {
+                                let p = (base + uvec3(x, y, z)).min(cells);
+                                values.push(grid.value(p.x, p.y, p.z));
+                            }
+                        }
+                    }
+
+                    let closest = values.iter().copied().fold(f32::MAX, |a, v| {
+                        if v.abs() < a.abs() {
+                            v
+                        } else {
+                            a
+                        }
+                    });
+                    if closest.abs() > band {
+                        // Points of the brick are within half a cell diagonal
+                        // of a sample.
+                        bricks.push(Brick::Constant(closest - closest.signum() * half_diagonal));
+                    } else {
+                        bricks.push(Brick::Dense(values));
+                    }
+                }
+            }
+        }
+
+        BrickGrid {
+            min: grid.min,
+            max: grid.max,
+            resolution: grid.resolution,
+            brick_size,
+            bricks_count,
+            bricks,
+        }
+    }
+
+    pub fn cell_size(&self) -> Vec3 {
+        (self.max - self.min) / (self.resolution - 1).as_vec3()
+    }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        p.cmpge(self.min).all() && p.cmple(self.max).all()
+    }
+
+    /// Number of bricks keeping their samples.
+    pub fn dense_bricks(&self) -> usize {
+        self.bricks
+            .iter()
+            .filter(|b| matches!(b, Brick::Dense(_)))
+            .count()
+    }
+
+    pub fn sample(&self, p: Vec3) -> f32 {
+        let q = p.clamp(self.min, self.max);
+        let outside = (p - q).length();
+
+        let g = (q - self.min) / self.cell_size();
+        let i = g.floor().as_uvec3().min(self.resolution - 2);
+        let t = g - i.as_vec3();
+
+        let b = i / self.brick_size;
+        let c = self.bricks_count;
+        let values = match &self.bricks[(b.x + c.x * (b.y + c.y * b.z)) as usize] {
+            Brick::Constant(v) => return outside_bound(*v, outside),
+            Brick::Dense(values) => values,
+        };
+
+        let side = self.brick_size + 1;
+        let l = i - b * self.brick_size;
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let c = |dx, dy, dz| values[(l.x + dx + side * (l.y + dy + side * (l.z + dz))) as usize];
+        let x00 = lerp(c(0, 0, 0), c(1, 0, 0), t.x);
+        let x10 = lerp(c(0, 1, 0), c(1, 1, 0), t.x);
+        let x01 = lerp(c(0, 0, 1), c(1, 0, 1), t.x);
+        let x11 = lerp(c(0, 1, 1), c(1, 1, 1), t.x);
+        let y0 = lerp(x00, x10, t.y);
+        let y1 = lerp(x01, x11, t.y);
+        outside_bound(lerp(y0, y1, t.z), outside)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec3;
+
+    fn sphere(p: Vec3) -> f32 {
+        p.length() - 1.
+    }
+
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..1000).map(|i| {
+            vec3(
+                (i % 10) as f32 * 0.51 - 2.37,
+                (i * 7 % 13) as f32 * 0.39 - 2.41,
+                (i * 11 % 17) as f32 * 0.29 - 2.33,
+            )
+        })
+    }
+
+    #[test]
+    fn constant_bricks_bound_the_distance() {
+        let grid = DistanceGrid::bake(Vec3::splat(-2.), Vec3::splat(2.), 33, sphere);
+        let band = grid.cell_size().length() * 2.;
+        let bricks = BrickGrid::from_grid(&grid, 4, band);
+        assert!(bricks.dense_bricks() > 0);
+        assert!(bricks.dense_bricks() < bricks.bricks.len());
+
+        let mut constant = 0;
+        for p in points().filter(|p| grid.contains(*p)) {
+            let exact = sphere(p);
+            let sampled = bricks.sample(p);
+            let b = ((p - grid.min) / grid.cell_size()).floor().as_uvec3() / bricks.brick_size;
+            let c = bricks.bricks_count;
+            match bricks.bricks[(b.x + c.x * (b.y + c.y * b.z)) as usize] {
+                Brick::Constant(_) => {
+                    constant += 1;
+                    assert_eq!(sampled.signum(), exact.signum(), "{p}");
+                    assert!(
+                        sampled.abs() <= exact.abs(),
+                        "{p}: {sampled} beyond {exact}"
+                    );
+                }
+                Brick::Dense(_) => assert!((sampled - grid.sample(p)).abs() < 1e-5, "{p}"),
+            }
+            if exact.abs() < band * 0.5 {
+                assert!(
+                    (sampled - grid.sample(p)).abs() < 1e-5,
+                    "{p} is in a constant brick"
+                );
+            }
+        }
+        assert!(constant > 0);
+    }
+}
@@ -1,5 +1,11 @@
 pub mod ray_marching;
 pub mod utils;
 pub mod sdfs;
+pub mod simd;
+pub mod sdf_tree;
+pub mod distance_grid;
+pub mod baked;
+pub mod packet;
+pub mod terrain;
 
-pub use ray_marching::RayMarching;
+pub use ray_marching::{take_march_stats, MarchStats, RayMarching};
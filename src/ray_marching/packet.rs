@@ -0,0 +1,130 @@
+use glam::{vec3, BVec4A, UVec4, Vec3, Vec4};
+
+use crate::ray::Ray;
+use crate::scene::Hit;
+
+use super::ray_marching::{record_march, HIT_PRECISION, MAX_STEPS};
+use super::simd::{self, Vec3x4};
+use super::RayMarching;
+
+impl<'a> RayMarching<'a> {
+    /// Whether the SDF can be evaluated on four lanes at once: the scene's
+    /// SDF is its node tree and no material displaces the surface.
+    pub fn supports_packets(&self) -> bool {
+        self.scene.evaluates_tree()
+            && self.scene.tree.is_some()
+            && self
+                .scene
+                .materials
+                .iter()
+                .all(|m| m.displacement.is_none())
+    }
+
+    /// Distances and materials at four points. Only meaningful when
+    /// `supports_packets` holds.
+    pub fn distance4(&self, p: Vec3x4) -> (Vec4, UVec4) {
+        match &self.scene.tree {
+            Some(tree) => tree.distance4(self.scene, p),
+            None => (Vec4::splat(f32::MAX), UVec4::ZERO),
+        }
+    }
+
+    /// Marches four rays in lockstep, evaluating the SDF once per step for
+    /// all of them. Rays that hit or leave the scene are masked off while
+    /// the others carry on. Falls back to `march_ray` for each ray when the
+    /// scene does not support packets.
+    pub fn march_packet(&self, rays: &[Ray; 4]) -> [Option<Hit>; 4] {
+        if !self.supports_packets() {
+            return rays.each_ref().map(|ray| self.march_ray(ray));
+        }
+
+        let origin = Vec3x4::from_array(rays.each_ref().map(|r| r.origin));
+        let direction = Vec3x4::from_array(rays.each_ref().map(|r| r.direction));
+        let max_distance = Vec4::splat(self.scene.max_distance);
+        let precision = Vec4::splat(HIT_PRECISION);
+
+        let mut t = Vec4::ZERO;
+        let mut active = BVec4A::splat(true);
+        let mut hit = BVec4A::splat(false);
+        let mut materials = UVec4::ZERO;
+        let mut steps = [0; 4];
+        for _ in 0..MAX_STEPS {
+            active &= t.cmple(max_distance);
+            if !active.any() {
+                break;
+            }
+
+            let (d, m) = self.distance4(origin + direction * t);
+            t = Vec4::select(active, t + d, t);
+            for (i, s) in steps.iter_mut().enumerate() {
+                *s += active.test(i) as usize;
+            }
+
+            let arrived = active & d.cmplt(precision);
+            materials = simd::select_u(arrived, m, materials);
+            hit |= arrived;
+            active &= !arrived;
+        }
+
+        std::array::from_fn(|i| {
+            record_march(steps[i], hit.test(i));
+            if !hit.test(i) {
+                return None;
+            }
+            let material_index = materials[i] as usize;
+            Some(Hit {
+                dist: t[i],
+                material_index,
+                color: self.scene.materials[material_index].albedo,
+                normal: None,
+            })
+        })
+    }
+
+    /// Soft shadows along four rays, marched together as in `shadow`. Lanes
+    /// not set in `mask` are not marched and stay lit.
+    pub fn shadow_packet(&self, rays: &[Ray; 4], mask: BVec4A, k: f32) -> Vec4 {
+        if !self.supports_packets() {
+            return Vec4::from_array(std::array::from_fn(|i| {
+                if mask.test(i) {
+                    self.shadow(&rays[i], k)
+                } else {
+                    1.
+                }
+            }));
+        }
+
+        let origin = Vec3x4::from_array(rays.each_ref().map(|r| r.origin));
+        let direction = Vec3x4::from_array(rays.each_ref().map(|r| r.direction));
+
+        let mut res = Vec4::ONE;
+        let mut t = Vec4::splat(0.01);
+        let mut active = mask;
+        for _ in 0..64 {
+            let pos = origin + direction * t;
+            let (h, _) = self.distance4(pos);
+            res = Vec4::select(active, res.min(k * h.max(Vec4::ZERO) / t), res);
+            active &= !(res.cmplt(Vec4::splat(0.0001)) | pos.y.cmpgt(Vec4::splat(10.)));
+            if !active.any() {
+                break;
+            }
+            let step = h.clamp(Vec4::splat(0.01), Vec4::splat(5.));
+            t = Vec4::select(active, t + step, t);
+        }
+        res
+    }
+
+    /// Tetrahedral normal with its four taps evaluated as one packet.
+    pub(super) fn packet_normal(&self, p: Vec3) -> Vec3 {
+        let k = 0.5773 * 0.0005;
+        let taps = [
+            vec3(1., -1., -1.),
+            vec3(-1., -1., 1.),
+            vec3(-1., 1., -1.),
+            vec3(1., 1., 1.),
+        ];
+        let (d, _) = self.distance4(Vec3x4::from_array(taps.map(|e| p + e * k)));
+
+        (taps[0] * d.x + taps[1] * d.y + taps[2] * d.z + taps[3] * d.w).normalize()
+    }
+}
@@ -1,4 +1,5 @@
 use core::f32;
+use std::cell::Cell;
 
 use glam::{vec2, vec3, Vec3};
 
@@ -7,18 +8,99 @@ use crate::ray::Ray;
 use crate::scene::{Hit, Scene};
 use crate::utils::materials::Material;
 
-static MAX_STEPS: usize = 300;
-static MAX_DISTANCE: f32 = 40.;
+pub(super) static MAX_STEPS: usize = 300;
 pub static HIT_PRECISION: f32 = 0.0001;
+static DISPLACEMENT_BAND: f32 = 0.05;
 static INV_PI: f32 = 1. / f32::consts::PI;
 
+/// Counters of the rays marched by `RayMarching::march_ray`.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MarchStats {
+    pub rays: u64,
+    pub hits: u64,
+    pub steps: u64,
+    pub max_steps: u32,
+}
+
+impl MarchStats {
+    pub fn merge(&self, other: &MarchStats) -> MarchStats {
+        MarchStats {
+            rays: self.rays + other.rays,
+            hits: self.hits + other.hits,
+            steps: self.steps + other.steps,
+            max_steps: self.max_steps.max(other.max_steps),
+        }
+    }
+
+    pub fn average_steps(&self) -> f32 {
+        self.steps as f32 / self.rays.max(1) as f32
+    }
+
+    pub fn hit_ratio(&self) -> f32 {
+        self.hits as f32 / self.rays.max(1) as f32
+    }
+}
+
+thread_local! {
+    static MARCH_STATS: Cell<MarchStats> = Cell::new(MarchStats::default());
+}
+
+/// Returns the counters of the current thread and resets them.
+pub fn take_march_stats() -> MarchStats {
+    MARCH_STATS.with(|s| s.take())
+}
+
+pub(super) fn record_march(steps: usize, hit: bool) {
+    MARCH_STATS.with(|s| {
+        let mut stats = s.get();
+        stats.rays += 1;
+        stats.hits += hit as u64;
+        stats.steps += steps as u64;
+        stats.max_steps = stats.max_steps.max(steps as u32);
+        s.set(stats);
+    });
+}
+
 #[derive(Debug, Clone)]
 pub struct RayMarching<'a> {
     pub scene: &'a Scene,
 }
 
 impl<'a> RayMarching<'a> {
+    /// Evaluates the scene SDF, offsetting it by the hit material's displacement map.
+    pub fn distance(&self, ray: &Ray, t: f32) -> Hit {
+        let hit = (self.scene.sdf)(self.scene, ray, t);
+
+        let Some(displacement) = self.scene.materials[hit.material_index].displacement else {
+            return hit;
+        };
+
+        // Far from the surface the displaced field is bounded by the undisplaced one.
+        if hit.dist > displacement.amplitude + DISPLACEMENT_BAND {
+            return Hit {
+                dist: hit.dist - displacement.amplitude,
+                ..hit
+            };
+        }
+
+        let p = ray.origin + ray.direction * t;
+        let n = self.tetrahedral_normal(p, |r, k| (self.scene.sdf)(self.scene, r, k).dist);
+        let h = displacement.height(p, n, &self.scene.textures);
+
+        Hit {
+            dist: (hit.dist - h) * displacement.step_scale(),
+            ..hit
+        }
+    }
+
     pub fn normal(&self, p: Vec3) -> Vec3 {
+        if self.supports_packets() {
+            return self.packet_normal(p);
+        }
+        self.tetrahedral_normal(p, |r, k| self.distance(r, k).dist)
+    }
+
+    fn tetrahedral_normal(&self, p: Vec3, f: impl Fn(&Ray, f32) -> f32) -> Vec3 {
         let k = 0.5773 * 0.0005;
         let e = vec2(1., -1.);
 
@@ -43,12 +125,8 @@ impl<'a> RayMarching<'a> {
             origin: p,
             direction: xxx,
         };
-        let f = self.scene.sdf;
 
-        (xyy * f(self.scene, &r_xyy, k).dist
-            + yyx * f(self.scene, &r_yyx, k).dist
-            + yxy * f(self.scene, &r_yxy, k).dist
-            + xxx * f(self.scene, &r_xxx, k).dist)
+        (xyy * f(&r_xyy, k) + yyx * f(&r_yyx, k) + yxy * f(&r_yxy, k) + xxx * f(&r_xxx, k))
             .normalize()
     }
 
@@ -58,9 +136,7 @@ impl<'a> RayMarching<'a> {
         for i in 0..5 {
             let hr = 0.02 + 0.025 * (i * i) as f32;
             //let aopos = nor * hr + pos;
-            let f = self.scene.sdf;
-            let dd = f(
-                self.scene,
+            let dd = self.distance(
                 &Ray {
                     origin: pos,
                     direction: nor,
@@ -73,6 +149,24 @@ impl<'a> RayMarching<'a> {
         return 1.0 - occ.clamp(0.0, 1.0);
     }
 
+    /// Estimates how much light crosses the solid below `pos` by probing the SDF along `-nor`.
+    pub fn translucency(&self, pos: Vec3, nor: Vec3, radius: f32) -> f32 {
+        let mut t = 0.0f32;
+        for i in 1..=5 {
+            let h = radius * i as f32 / 5.;
+            let dd = self.distance(
+                &Ray {
+                    origin: pos,
+                    direction: -nor,
+                },
+                h,
+            );
+            // Deep inside the distance is -h, thin parts come out closer to the surface.
+            t += ((h + dd.dist) / h).clamp(0.0, 1.0);
+        }
+        t / 5.
+    }
+
     pub fn shadow(&self, ray: &Ray, k: f32) -> f32 {
         let mut res = 1.0f32;
 
@@ -80,7 +174,7 @@ impl<'a> RayMarching<'a> {
         let mut i = 0;
         while i < 64 {
             let pos = ray.origin + ray.direction * t;
-            let h = (self.scene.sdf)(self.scene, ray, t).dist;
+            let h = self.distance(ray, t).dist;
             res = res.min(k * (h.max(0.0) / t));
             if res < 0.0001 || pos.y > 10.0 {
                 break;
@@ -98,18 +192,38 @@ impl<'a> RayMarching<'a> {
         // March the ray
         let mut i = 0;
         while i < MAX_STEPS {
-            if t > MAX_DISTANCE {
+            if t > self.scene.max_distance {
                 break;
             }
 
-            let h = (self.scene.sdf)(self.scene, ray, t);
+            let h = self.distance(ray, t);
             t += h.dist;
             if h.dist < HIT_PRECISION {
-                return Some(Hit {
-                    dist: t,
-                    material_index: h.material_index,
-                    color: h.color,
-                });
+                record_march(i + 1, true);
+                return Some(Hit { dist: t, ..h });
+            }
+            i += 1;
+        }
+
+        record_march(i, false);
+        None
+    }
+
+    /// Marches a ray starting inside a solid until it reaches the boundary.
+    pub fn march_ray_inside(&self, ray: &Ray) -> Option<Hit> {
+        let mut t = 0.0;
+
+        let mut i = 0;
+        while i < MAX_STEPS {
+            if t > self.scene.max_distance {
+                break;
+            }
+
+            let h = self.distance(ray, t);
+            let d = -h.dist;
+            t += d;
+            if d < HIT_PRECISION {
+                return Some(Hit { dist: t, ..h });
             }
             i += 1;
         }
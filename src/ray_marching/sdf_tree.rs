@@ -0,0 +1,398 @@
+use std::sync::Arc;
+
+use glam::{Mat3, UVec4, Vec3, Vec4};
+
+use crate::mesh::bvh::Aabb;
+use crate::mesh::Mesh;
+use crate::scene::Scene;
+use crate::utils::errors::AppError;
+use crate::utils::math;
+
+use super::baked::{BakeSettings, BakedVolume};
+use super::distance_grid::DistanceGrid;
+use super::sdfs::{
+    box_sdf, box_sdf4, cylinder_sdf, cylinder_sdf4, line_sdf, line_sdf4, plane_sdf, plane_sdf4,
+    sphere_sdf, sphere_sdf4, torus_sdf, torus_sdf4,
+};
+use super::simd::{self, Vec3x4};
+
+/// Analytic primitive, positioned in the space of its parent node.
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    Box {
+        center: Vec3,
+        half_size: Vec3,
+        corner_radius: f32,
+    },
+    Plane {
+        point: Vec3,
+        normal: Vec3,
+    },
+    Cylinder {
+        center: Vec3,
+        radius: f32,
+        height: f32,
+        corner_radius: f32,
+    },
+    Capsule {
+        a: Vec3,
+        b: Vec3,
+        radius: f32,
+    },
+    Torus {
+        center: Vec3,
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// Imported triangle mesh, evaluated exactly through its BVH.
+    Mesh(Arc<Mesh>),
+    /// Distances baked on a grid, cheaper than the source they came from.
+    Grid(Arc<DistanceGrid>),
+}
+
+impl Shape {
+    pub fn distance(&self, p: Vec3) -> f32 {
+        match self {
+            Shape::Sphere { center, radius } => sphere_sdf(p - *center, *radius),
+            Shape::Box {
+                center,
+                half_size,
+                corner_radius,
+            } => box_sdf(p - *center, *half_size, *corner_radius),
+            Shape::Plane { point, normal } => plane_sdf(p, *point, *normal),
+            Shape::Cylinder {
+                center,
+                radius,
+                height,
+                corner_radius,
+            } => cylinder_sdf(p - *center, *radius, *corner_radius, *height),
+            Shape::Capsule { a, b, radius } => line_sdf(p, *a, *b, *radius),
+            Shape::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => torus_sdf(p - *center, *major_radius, *minor_radius),
+            Shape::Mesh(mesh) => mesh.signed_distance(p),
+            Shape::Grid(grid) => grid.sample(p),
+        }
+    }
+
+    /// Distances at four points at once. Meshes and grids are evaluated one
+    /// lane at a time.
+    pub fn distance4(&self, p: Vec3x4) -> Vec4 {
+        match self {
+            Shape::Sphere { center, radius } => sphere_sdf4(p - *center, *radius),
+            Shape::Box {
+                center,
+                half_size,
+                corner_radius,
+            } => box_sdf4(p - *center, *half_size, *corner_radius),
+            Shape::Plane { point, normal } => plane_sdf4(p, *point, *normal),
+            Shape::Cylinder {
+                center,
+                radius,
+                height,
+                corner_radius,
+            } => cylinder_sdf4(p - *center, *radius, *corner_radius, *height),
+            Shape::Capsule { a, b, radius } => line_sdf4(p, *a, *b, *radius),
+            Shape::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => torus_sdf4(p - *center, *major_radius, *minor_radius),
+            Shape::Mesh(_) | Shape::Grid(_) => {
+                Vec4::from_array(p.to_array().map(|p| self.distance(p)))
+            }
+        }
+    }
+
+    /// Box enclosing the surface, none for planes.
+    pub fn bounds(&self) -> Option<Aabb> {
+        let around = |center: Vec3, half_size: Vec3| Aabb {
+            min: center - half_size,
+            max: center + half_size,
+        };
+        match self {
+            Shape::Sphere { center, radius } => Some(around(*center, Vec3::splat(*radius))),
+            Shape::Box {
+                center, half_size, ..
+            } => Some(around(*center, *half_size)),
+            Shape::Plane { .. } => None,
+            Shape::Cylinder {
+                center,
+                radius,
+                height,
+                ..
+            } => Some(around(*center, Vec3::new(*radius, height * 0.5, *radius))),
+            Shape::Capsule { a, b, radius } => Some(Aabb::from_points(&[*a, *b]).expand(*radius)),
+            Shape::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let r = major_radius + minor_radius;
+                Some(around(*center, Vec3::new(r, *minor_radius, r)))
+            }
+            Shape::Mesh(mesh) => Some(mesh.bounds),
+            Shape::Grid(grid) => Some(Aabb {
+                min: grid.min,
+                max: grid.max,
+            }),
+        }
+    }
+}
+
+/// Tree of primitives combined with boolean operators, as loaded from scene
+/// files. Leaves carry the index of their material in `Scene::materials`.
+#[derive(Debug, Clone)]
+pub enum SdfNode {
+    Primitive {
+        shape: Shape,
+        material: usize,
+    },
+    Union(Vec<SdfNode>),
+    /// Polynomial smooth minimum of the children, blending over `k`.
+    SmoothUnion {
+        k: f32,
+        children: Vec<SdfNode>,
+    },
+    Intersection(Vec<SdfNode>),
+    /// First child with the others carved out of it.
+    Subtraction(Vec<SdfNode>),
+    /// Child placed by `Scene::transforms[transform]`, so the timeline can
+    /// move it.
+    Transformed {
+        transform: usize,
+        child: Box<SdfNode>,
+    },
+    /// Child sampled from a grid once `SdfNode::bake` has run, and evaluated
+    /// exactly only close to its surface.
+    Baked {
+        settings: BakeSettings,
+        volume: Option<Arc<BakedVolume>>,
+        child: Box<SdfNode>,
+    },
+}
+
+impl SdfNode {
+    /// Calls `f` on this node and all its descendants.
+    pub fn visit(&self, f: &mut impl FnMut(&SdfNode)) {
+        f(self);
+        match self {
+            SdfNode::Primitive { .. } => {}
+            SdfNode::Union(children)
+            | SdfNode::SmoothUnion { children, .. }
+            | SdfNode::Intersection(children)
+            | SdfNode::Subtraction(children) => children.iter().for_each(|c| c.visit(f)),
+            SdfNode::Transformed { child, .. } | SdfNode::Baked { child, .. } => child.visit(f),
+        }
+    }
+
+    /// Bakes the volumes of the `Baked` nodes below this one.
+    pub fn bake(&mut self, scene: &Scene) -> Result<(), AppError> {
+        match self {
+            SdfNode::Primitive { .. } => Ok(()),
+            SdfNode::Union(children)
+            | SdfNode::SmoothUnion { children, .. }
+            | SdfNode::Intersection(children)
+            | SdfNode::Subtraction(children) => children.iter_mut().try_for_each(|c| c.bake(scene)),
+            SdfNode::Transformed { child, .. } => child.bake(scene),
+            SdfNode::Baked {
+                settings,
+                volume,
+                child,
+            } => {
+                child.bake(scene)?;
+                *volume = Some(Arc::new(BakedVolume::bake(child, scene, settings)?));
+                Ok(())
+            }
+        }
+    }
+
+    /// Box enclosing the surface, none when it is unbounded.
+    pub fn bounds(&self, scene: &Scene) -> Option<Aabb> {
+        match self {
+            SdfNode::Primitive { shape, .. } => shape.bounds(),
+            SdfNode::Union(children) => children
+                .iter()
+                .try_fold(Aabb::EMPTY, |b, c| Some(b.union(&c.bounds(scene)?))),
+            SdfNode::SmoothUnion { k, children } => children
+                .iter()
+                .try_fold(Aabb::EMPTY, |b, c| Some(b.union(&c.bounds(scene)?)))
+                .map(|b| b.expand(*k)),
+            SdfNode::Intersection(children) => children
+                .iter()
+                .filter_map(|c| c.bounds(scene))
+                .reduce(|a, b| Aabb {
+                    min: a.min.max(b.min),
+                    max: a.max.min(b.max),
+                }),
+            SdfNode::Subtraction(children) => children.first()?.bounds(scene),
+            SdfNode::Transformed { transform, child } => {
+                let b = child.bounds(scene)?;
+                let Some(t) = scene.transforms.get(*transform) else {
+                    return Some(b);
+                };
+                let corners: Vec<Vec3> = (0..8)
+                    .map(|c| {
+                        let pick = |bit: i32, lo: f32, hi: f32| if c & bit != 0 { hi } else { lo };
+                        t.to_world(Vec3::new(
+                            pick(1, b.min.x, b.max.x),
+                            pick(2, b.min.y, b.max.y),
+                            pick(4, b.min.z, b.max.z),
+                        ))
+                    })
+                    .collect();
+                Some(Aabb::from_points(&corners))
+            }
+            SdfNode::Baked {
+                settings, child, ..
+            } => settings.bounds.or_else(|| child.bounds(scene)),
+        }
+    }
+
+    /// Signed distance at `p` and the material of the closest leaf.
+    pub fn distance(&self, scene: &Scene, p: Vec3) -> (f32, usize) {
+        match self {
+            SdfNode::Primitive { shape, material } => (shape.distance(p), *material),
+            SdfNode::Union(children) => children
+                .iter()
+                .map(|c| c.distance(scene, p))
+                .fold((f32::MAX, 0), |a, b| if b.0 < a.0 { b } else { a }),
+            SdfNode::SmoothUnion { k, children } => {
+                let mut res = (f32::MAX, 0);
+                for (i, child) in children.iter().enumerate() {
+                    let (d, m) = child.distance(scene, p);
+                    res = if i == 0 {
+                        (d, m)
+                    } else {
+                        (
+                            math::smooth_min(res.0, d, *k),
+                            if d < res.0 { m } else { res.1 },
+                        )
+                    };
+                }
+                res
+            }
+            SdfNode::Intersection(children) => children
+                .iter()
+                .map(|c| c.distance(scene, p))
+                .fold((f32::MIN, 0), |a, b| if b.0 > a.0 { b } else { a }),
+            SdfNode::Subtraction(children) => {
+                let Some((first, rest)) = children.split_first() else {
+                    return (f32::MAX, 0);
+                };
+                let (mut d, m) = first.distance(scene, p);
+                for child in rest {
+                    d = d.max(-child.distance(scene, p).0);
+                }
+                (d, m)
+            }
+            SdfNode::Baked {
+                volume: Some(volume),
+                child,
+                ..
+            } => {
+                let d = volume.sample(p);
+                if d.abs() < volume.band {
+                    child.distance(scene, p)
+                } else {
+                    (d, volume.material)
+                }
+            }
+            SdfNode::Baked { child, .. } => child.distance(scene, p),
+            SdfNode::Transformed { transform, child } => match scene.transforms.get(*transform) {
+                Some(t) => {
+                    let (d, m) = child.distance(scene, t.to_local(p));
+                    (t.to_world_distance(d), m)
+                }
+                None => child.distance(scene, p),
+            },
+        }
+    }
+
+    /// Same as `distance` for four points at once, with the material of each
+    /// lane.
+    pub fn distance4(&self, scene: &Scene, p: Vec3x4) -> (Vec4, UVec4) {
+        match self {
+            SdfNode::Primitive { shape, material } => {
+                (shape.distance4(p), UVec4::splat(*material as u32))
+            }
+            SdfNode::Union(children) => {
+                let mut res = (Vec4::splat(f32::MAX), UVec4::ZERO);
+                for child in children {
+                    let (d, m) = child.distance4(scene, p);
+                    let closer = d.cmplt(res.0);
+                    res = (
+                        Vec4::select(closer, d, res.0),
+                        simd::select_u(closer, m, res.1),
+                    );
+                }
+                res
+            }
+            SdfNode::SmoothUnion { k, children } => {
+                let mut res = (Vec4::splat(f32::MAX), UVec4::ZERO);
+                for (i, child) in children.iter().enumerate() {
+                    let (d, m) = child.distance4(scene, p);
+                    res = if i == 0 {
+                        (d, m)
+                    } else {
+                        let closer = d.cmplt(res.0);
+                        (smooth_min4(res.0, d, *k), simd::select_u(closer, m, res.1))
+                    };
+                }
+                res
+            }
+            SdfNode::Intersection(children) => {
+                let mut res = (Vec4::splat(f32::MIN), UVec4::ZERO);
+                for child in children {
+                    let (d, m) = child.distance4(scene, p);
+                    let farther = d.cmpgt(res.0);
+                    res = (
+                        Vec4::select(farther, d, res.0),
+                        simd::select_u(farther, m, res.1),
+                    );
+                }
+                res
+            }
+            SdfNode::Subtraction(children) => {
+                let Some((first, rest)) = children.split_first() else {
+                    return (Vec4::splat(f32::MAX), UVec4::ZERO);
+                };
+                let (mut d, m) = first.distance4(scene, p);
+                for child in rest {
+                    d = d.max(-child.distance4(scene, p).0);
+                }
+                (d, m)
+            }
+            SdfNode::Transformed { transform, child } => match scene.transforms.get(*transform) {
+                Some(t) => {
+                    let inverse = Mat3::from_quat(t.rotation.inverse());
+                    let local = (p - t.translation).transform(&inverse) * (1. / t.scale);
+                    let (d, m) = child.distance4(scene, local);
+                    (d * t.scale, m)
+                }
+                None => child.distance4(scene, p),
+            },
+            // Baked volumes are sampled one lane at a time.
+            SdfNode::Baked { .. } => {
+                let lanes = p.to_array().map(|p| self.distance(scene, p));
+                (
+                    Vec4::from_array(lanes.map(|l| l.0)),
+                    UVec4::from_array(lanes.map(|l| l.1 as u32)),
+                )
+            }
+        }
+    }
+}
+
+/// `math::smooth_min` on four lanes.
+fn smooth_min4(d1: Vec4, d2: Vec4, k: f32) -> Vec4 {
+    let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(Vec4::ZERO, Vec4::ONE);
+
+    d2 * (1. - h) + d1 * h - k * h * (1. - h)
+}
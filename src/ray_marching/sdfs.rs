@@ -1,6 +1,8 @@
 use std::f32;
 
-use glam::{vec2, Vec2, Vec3};
+use glam::{vec2, Vec2, Vec3, Vec4};
+
+use super::simd::{self, Vec3x4};
 
 pub fn box_sdf(p: Vec3, dimension: Vec3, corner_radius: f32) -> f32 {
     let q = p.abs() - dimension + corner_radius;
@@ -30,3 +32,45 @@ pub fn line_sdf(p: Vec3, a: Vec3, b: Vec3, r: f32) -> f32 {
 
     (pa - h * ba).length() - r
 }
+
+pub fn torus_sdf(p: Vec3, major_radius: f32, minor_radius: f32) -> f32 {
+    vec2(vec2(p.x, p.z).length() - major_radius, p.y).length() - minor_radius
+}
+
+// Versions of the primitives evaluating four points at once.
+
+pub fn box_sdf4(p: Vec3x4, dimension: Vec3, corner_radius: f32) -> Vec4 {
+    let q = p.abs() - (dimension - corner_radius);
+
+    q.max(Vec4::ZERO).length() + q.max_element().min(Vec4::ZERO) - corner_radius
+}
+
+pub fn sphere_sdf4(p: Vec3x4, radius: f32) -> Vec4 {
+    p.length() - radius
+}
+
+pub fn plane_sdf4(p: Vec3x4, plane_point: Vec3, normal: Vec3) -> Vec4 {
+    (p - plane_point).dot_vec3(normal)
+}
+
+pub fn cylinder_sdf4(p: Vec3x4, radius: f32, corner_radius: f32, height: f32) -> Vec4 {
+    let dx = p.length_xz() - radius + corner_radius;
+    let dy = p.y.abs() - height * 0.5 + corner_radius;
+    let (mx, my) = (dx.max(Vec4::ZERO), dy.max(Vec4::ZERO));
+
+    simd::sqrt(mx * mx + my * my) + dx.max(dy).min(Vec4::ZERO) - corner_radius
+}
+
+pub fn line_sdf4(p: Vec3x4, a: Vec3, b: Vec3, r: f32) -> Vec4 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = (pa.dot_vec3(ba) / ba.dot(ba)).clamp(Vec4::ZERO, Vec4::ONE);
+
+    (pa - Vec3x4::splat(ba) * h).length() - r
+}
+
+pub fn torus_sdf4(p: Vec3x4, major_radius: f32, minor_radius: f32) -> Vec4 {
+    let q = p.length_xz() - major_radius;
+
+    simd::sqrt(q * q + p.y * p.y) - minor_radius
+}
@@ -0,0 +1,189 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use glam::{BVec4A, Mat3, UVec4, Vec3, Vec4};
+
+/// Four points or vectors stored as one SIMD register per axis, so each
+/// operation acts on the four lanes at once.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vec3x4 {
+    pub x: Vec4,
+    pub y: Vec4,
+    pub z: Vec4,
+}
+
+impl Vec3x4 {
+    pub const ZERO: Vec3x4 = Vec3x4::splat(Vec3::ZERO);
+
+    pub const fn splat(v: Vec3) -> Vec3x4 {
+        Vec3x4 {
+            x: Vec4::splat(v.x),
+            y: Vec4::splat(v.y),
+            z: Vec4::splat(v.z),
+        }
+    }
+
+    pub fn from_array(v: [Vec3; 4]) -> Vec3x4 {
+        Vec3x4 {
+            x: Vec4::new(v[0].x, v[1].x, v[2].x, v[3].x),
+            y: Vec4::new(v[0].y, v[1].y, v[2].y, v[3].y),
+            z: Vec4::new(v[0].z, v[1].z, v[2].z, v[3].z),
+        }
+    }
+
+    pub fn lane(&self, i: usize) -> Vec3 {
+        Vec3::new(self.x[i], self.y[i], self.z[i])
+    }
+
+    pub fn to_array(&self) -> [Vec3; 4] {
+        std::array::from_fn(|i| self.lane(i))
+    }
+
+    pub fn dot(&self, other: Vec3x4) -> Vec4 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn dot_vec3(&self, v: Vec3) -> Vec4 {
+        self.x * v.x + self.y * v.y + self.z * v.z
+    }
+
+    pub fn length(&self) -> Vec4 {
+        sqrt(self.dot(*self))
+    }
+
+    /// Length of the projection on the XZ plane.
+    pub fn length_xz(&self) -> Vec4 {
+        sqrt(self.x * self.x + self.z * self.z)
+    }
+
+    pub fn abs(&self) -> Vec3x4 {
+        Vec3x4 {
+            x: self.x.abs(),
+            y: self.y.abs(),
+            z: self.z.abs(),
+        }
+    }
+
+    pub fn max(&self, v: Vec4) -> Vec3x4 {
+        Vec3x4 {
+            x: self.x.max(v),
+            y: self.y.max(v),
+            z: self.z.max(v),
+        }
+    }
+
+    /// Largest of the three components of each lane.
+    pub fn max_element(&self) -> Vec4 {
+        self.x.max(self.y.max(self.z))
+    }
+
+    /// Transforms each lane by `m`.
+    pub fn transform(&self, m: &Mat3) -> Vec3x4 {
+        Vec3x4 {
+            x: self.x * m.x_axis.x + self.y * m.y_axis.x + self.z * m.z_axis.x,
+            y: self.x * m.x_axis.y + self.y * m.y_axis.y + self.z * m.z_axis.y,
+            z: self.x * m.x_axis.z + self.y * m.y_axis.z + self.z * m.z_axis.z,
+        }
+    }
+
+    /// Lanes of `a` where `mask` is set, of `b` elsewhere.
+    pub fn select(mask: BVec4A, a: Vec3x4, b: Vec3x4) -> Vec3x4 {
+        Vec3x4 {
+            x: Vec4::select(mask, a.x, b.x),
+            y: Vec4::select(mask, a.y, b.y),
+            z: Vec4::select(mask, a.z, b.z),
+        }
+    }
+}
+
+impl Add for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn add(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4 {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
+impl Sub for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn sub(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4 {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z,
+        }
+    }
+}
+
+impl Sub<Vec3> for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn sub(self, rhs: Vec3) -> Vec3x4 {
+        Vec3x4 {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z,
+        }
+    }
+}
+
+impl Mul<Vec4> for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn mul(self, rhs: Vec4) -> Vec3x4 {
+        Vec3x4 {
+            x: self.x * rhs,
+            y: self.y * rhs,
+            z: self.z * rhs,
+        }
+    }
+}
+
+impl Mul<f32> for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn mul(self, rhs: f32) -> Vec3x4 {
+        self * Vec4::splat(rhs)
+    }
+}
+
+impl Neg for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn neg(self) -> Vec3x4 {
+        Vec3x4 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
+/// Square root of each lane.
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+pub fn sqrt(v: Vec4) -> Vec4 {
+    use std::arch::x86_64::_mm_sqrt_ps;
+    // SAFETY: SSE2 is available, as checked by the cfg attribute.
+    unsafe { _mm_sqrt_ps(v.into()).into() }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "sse2")))]
+pub fn sqrt(v: Vec4) -> Vec4 {
+    Vec4::from_array(v.to_array().map(f32::sqrt))
+}
+
+/// Lanes of `a` where `mask` is set, of `b` elsewhere.
+pub fn select_u(mask: BVec4A, a: UVec4, b: UVec4) -> UVec4 {
+    let bits = mask.bitmask();
+    UVec4::from_array(std::array::from_fn(|i| {
+        if bits & (1 << i) != 0 {
+            a[i]
+        } else {
+            b[i]
+        }
+    }))
+}
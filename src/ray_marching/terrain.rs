@@ -0,0 +1,64 @@
+use glam::{vec2, vec3, Vec2, Vec3, Vec3Swizzles};
+
+use crate::utils::math;
+use crate::utils::noise::Fbm;
+use crate::utils::texture::Texture;
+
+#[derive(Debug, Copy, Clone)]
+pub enum HeightSource {
+    /// Gray scale height map covering `size` world units along x and z.
+    Texture { index: usize, size: f32 },
+    /// Procedural fBm, sampled in world units.
+    Fbm(Fbm),
+}
+
+/// Height field primitive: the surface `y = offset + height * h(x, z)`.
+#[derive(Debug, Copy, Clone)]
+pub struct Terrain {
+    pub source: HeightSource,
+    pub height: f32,
+    pub offset: f32,
+    /// Upper bound of the surface slope, used to keep marching steps conservative.
+    pub lipschitz: f32,
+    /// Distance at which the fBm loses its first octave; each doubling drops another.
+    pub lod_distance: f32,
+    pub min_octaves: u32,
+}
+
+impl Terrain {
+    /// Octaves worth evaluating for a sample `t` units away from the eye.
+    pub fn octaves(&self, fbm: &Fbm, t: f32) -> u32 {
+        let dropped = (1. + t / self.lod_distance).log2() as u32;
+        fbm.octaves
+            .saturating_sub(dropped)
+            .max(self.min_octaves.min(fbm.octaves))
+    }
+
+    /// Surface height and its gradient along x and z.
+    pub fn height(&self, textures: &[Texture], xz: Vec2, t: f32) -> (f32, Vec2) {
+        let (h, dh) = match self.source {
+            HeightSource::Texture { index, size } => {
+                let tex = &textures[index];
+                let uv = xz / size;
+                let e = 1. / tex.width.max(tex.height) as f32;
+                let h = math::luminance(tex.from_uv_bilinear(uv.x, uv.y));
+                let hu = math::luminance(tex.from_uv_bilinear(uv.x + e, uv.y));
+                let hv = math::luminance(tex.from_uv_bilinear(uv.x, uv.y + e));
+                (h, vec2(hu - h, hv - h) / (e * size))
+            }
+            HeightSource::Fbm(fbm) => Fbm {
+                octaves: self.octaves(&fbm, t),
+                ..fbm
+            }
+            .sample2(xz),
+        };
+        (self.offset + self.height * h, self.height * dh)
+    }
+
+    /// Conservative distance from `p` to the surface, and the surface normal below it.
+    pub fn sdf(&self, textures: &[Texture], p: Vec3, t: f32) -> (f32, Vec3) {
+        let (h, dh) = self.height(textures, p.xz(), t);
+        let step_scale = 1. / (1. + self.lipschitz * self.lipschitz).sqrt();
+        ((p.y - h) * step_scale, vec3(-dh.x, 1., -dh.y).normalize())
+    }
+}
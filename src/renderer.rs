@@ -1,22 +1,120 @@
-use std::num;
+use std::time::{Duration, Instant};
 
-use glam::{vec2, Vec3, Vec4};
+use glam::{vec2, Vec2, Vec3};
 use sdl2::render::Texture;
 
+use crate::denoiser::Denoiser;
+use crate::ray_marching::{take_march_stats, MarchStats, RayMarching};
+use crate::scene::Aov;
+use crate::tiles::{Tile, TileOrder};
 use crate::{camera::Camera, scene::Scene};
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelBridge,
+    ParallelIterator,
+};
 
-struct Chunk {
-    size: usize,
-    pixel_offset: usize,
+/// Samples kept from the reprojected history, so lighting can still adapt.
+static TEMPORAL_MAX_HISTORY: u32 = 16;
+/// Relative depth difference above which a reprojected sample is rejected.
+static TEMPORAL_DEPTH_TOLERANCE: f32 = 0.05;
+static TEMPORAL_NORMAL_TOLERANCE: f32 = 0.9;
+/// Side of the pixel blocks shaded once during the preview pass.
+static PREVIEW_BLOCK: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Analytic lighting, re-rendered only when something changed.
+    Fast,
+    /// Progressive path tracing, accumulating one sample per frame.
+    PathTraced,
 }
 
-pub struct Renderer {}
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Pass {
+    /// Coarse blocks giving quick feedback after a change.
+    Preview,
+    Full,
+}
+
+/// Measurements of the last `render` call.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RenderStats {
+    pub frame_time: Duration,
+    /// Primary hit AOVs and temporal reprojection after a change.
+    pub restart_time: Duration,
+    pub tiles_time: Duration,
+    pub denoise_time: Duration,
+    pub upload_time: Duration,
+    pub tiles: usize,
+    pub pending_tiles: usize,
+    pub average_tile_time: Duration,
+    pub max_tile_time: Duration,
+    pub march: MarchStats,
+    pub rays_per_second: f64,
+}
+
+pub struct Renderer {
+    pub mode: RenderMode,
+    /// Reuses the previous frames' samples when the camera moves.
+    pub temporal: bool,
+    /// Filters the accumulated image before display.
+    pub denoise: bool,
+    pub denoiser: Denoiser,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    /// Renders a low resolution pass first whenever the view changes.
+    pub preview: bool,
+    /// Time allowed per `render` call; unfinished tiles carry over to the next call.
+    pub frame_budget: Option<Duration>,
+    /// Marches the camera and shadow rays of the fast mode four at a time,
+    /// for scenes supporting packets.
+    pub packets: bool,
+    pending: Vec<Tile>,
+    pass: Pass,
+    pass_time: Duration,
+    resolution: Vec2,
+    accumulation: Vec<Vec3>,
+    counts: Vec<u32>,
+    aovs: Vec<Aov>,
+    previous: Option<Camera>,
+    samples: u32,
+    stats: RenderStats,
+}
 
 impl Renderer {
     pub fn new() -> Renderer {
-        Renderer {}
+        Renderer {
+            mode: RenderMode::Fast,
+            temporal: true,
+            denoise: true,
+            denoiser: Denoiser::default(),
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            preview: true,
+            frame_budget: Some(Duration::from_millis(33)),
+            packets: true,
+            pending: vec![],
+            pass: Pass::Full,
+            pass_time: Duration::ZERO,
+            resolution: Vec2::ZERO,
+            accumulation: vec![],
+            counts: vec![],
+            aovs: vec![],
+            previous: None,
+            samples: 0,
+            stats: RenderStats::default(),
+        }
+    }
+
+    /// Number of samples accumulated per pixel in path traced mode.
+    pub fn samples(&self) -> u32 {
+        self.samples
     }
+
+    pub fn stats(&self) -> &RenderStats {
+        &self.stats
+    }
+
     pub fn to_rgba(c: Vec3) -> (u8, u8, u8, u8) {
         (
             (c.x * 255.) as u8,
@@ -26,68 +124,339 @@ impl Renderer {
         )
     }
 
-    fn render_chunk(
-        scene: &Scene,
+    fn write_pixel(img: &mut [u8], i: usize, c: Vec3) {
+        let color = Self::to_rgba(c.clamp(Vec3::ZERO, Vec3::ONE));
+        img[i * 4..i * 4 + 4].copy_from_slice(&[color.0, color.1, color.2, color.3]);
+    }
+
+    /// Renders tiles until the frame budget runs out and uploads the image.
+    /// Returns the time spent on the frame once its full resolution pass completes.
+    pub fn render(
+        &mut self,
+        scene: &mut Scene,
+        texture: &mut Texture,
+        img: &mut [u8],
         camera: &Camera,
-        num_pixels: usize,
-        offset: usize,
-        bytes: &mut [u8],
-    ) {
-        let mut i = 0;
+        updated: bool,
+    ) -> Result<Option<Duration>, String> {
+        let Some(frame_time) = self.render_frame(scene, img, camera, updated) else {
+            return Ok(None);
+        };
 
-        let mut pos = 0;
-        let res_x = camera.resolution.x as usize;
-        let res_y = camera.resolution.x as usize;
-        while pos < num_pixels {
-            let off = pos + offset;
-            let y = off / res_x;
-            let x = off - (y * res_y);
+        let upload = Instant::now();
+        texture
+            .update(None, img, camera.resolution.x as usize * 4)
+            .map_err(|e| e.to_string())?;
+        self.stats.upload_time = upload.elapsed();
+        self.stats.frame_time += self.stats.upload_time;
 
-            let p = scene.color(camera, vec2(x as f32, y as f32));
+        Ok(frame_time)
+    }
 
-            let color = Self::to_rgba(p.clamp(Vec3::ZERO, Vec3::ONE));
+    /// Renders a complete image without a window, accumulating `samples`
+    /// passes in path traced mode.
+    pub fn render_image(&mut self, scene: &Scene, camera: &Camera, samples: u32) -> Vec<u8> {
+        self.render_image_with_progress(scene, camera, samples, |_| {})
+    }
+
+    /// Same as `render_image`, calling `progress` with the number of samples
+    /// accumulated after each pass.
+    pub fn render_image_with_progress(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        samples: u32,
+        mut progress: impl FnMut(u32),
+    ) -> Vec<u8> {
+        let num_pixels = (camera.resolution.x * camera.resolution.y) as usize;
+        let mut img = vec![0; num_pixels * 4];
 
-            bytes[i] = color.0;
-            bytes[i + 1] = color.1;
-            bytes[i + 2] = color.2;
-            bytes[i + 3] = color.3;
+        let budget = self.frame_budget.take();
+        let preview = std::mem::replace(&mut self.preview, false);
+        // Every image starts from scratch, without reprojected history.
+        self.previous = None;
 
-            i += 4;
-            pos += 1;
+        self.render_frame(scene, &mut img, camera, true);
+        progress(self.samples);
+        while self.mode == RenderMode::PathTraced && self.samples < samples {
+            self.render_frame(scene, &mut img, camera, false);
+            progress(self.samples);
         }
+
+        self.frame_budget = budget;
+        self.preview = preview;
+        img
     }
 
-    pub fn render(
-        &self,
-        scene: &mut Scene,
-        texture: &mut Texture,
-        img: &mut Vec<u8>,
+    /// Advances the current frame. Returns None when there was nothing to do,
+    /// otherwise the frame time if a full pass completed.
+    fn render_frame(
+        &mut self,
+        scene: &Scene,
+        img: &mut [u8],
         camera: &Camera,
         updated: bool,
-        num_chunks: usize,
-    ) -> Result<(), String> {
-        if !updated {
-            return Ok(())
+    ) -> Option<Option<Duration>> {
+        let width = camera.resolution.x as usize;
+        let height = camera.resolution.y as usize;
+        let resized = camera.resolution != self.resolution;
+        let frame_start = Instant::now();
+        let mut stats = RenderStats::default();
+
+        if updated || resized {
+            // Whatever is left of the previous frame is dropped.
+            self.resolution = camera.resolution;
+            if self.mode == RenderMode::PathTraced {
+                self.restart(scene, camera);
+            }
+            stats.restart_time = frame_start.elapsed();
+            self.pass_time = Duration::ZERO;
+            let pass = if self.preview {
+                Pass::Preview
+            } else {
+                Pass::Full
+            };
+            self.start_pass(pass, width, height);
+        } else if self.pending.is_empty() {
+            if self.pass == Pass::Full && self.mode == RenderMode::Fast {
+                return None;
+            }
+            self.start_pass(Pass::Full, width, height);
         }
-        let img_len = img.len();
-        let img_chunk_size = (img_len / (num_chunks * 4)) * 4;
 
-        let chunks: Vec<(usize, &mut [u8])> = img.chunks_mut(img_chunk_size).enumerate().collect();
+        let start = Instant::now();
 
-        chunks.into_par_iter().for_each(|e| {
-            let buf_len = e.1.len();
+        let deadline = self.frame_budget.map(|b| start + b);
+        let rendered: Vec<_> = self
+            .pending
+            .iter()
+            .enumerate()
+            .par_bridge()
+            .filter_map(|(i, tile)| {
+                if deadline.is_some_and(|d| Instant::now() > d) {
+                    return None;
+                }
+                // Drop whatever this thread marched outside of tiles.
+                take_march_stats();
+                let tile_start = Instant::now();
+                let colors = self.render_tile(scene, camera, tile);
+                Some((i, colors, tile_start.elapsed(), take_march_stats()))
+            })
+            .collect();
 
-            let num_pixels = buf_len / 4;
+        let mut done = vec![false; self.pending.len()];
+        for (i, colors, time, march) in rendered {
+            done[i] = true;
+            self.merge_tile(self.pending[i], &colors, img, width);
 
-            let offset = e.0 * num_pixels;
+            stats.tiles += 1;
+            stats.average_tile_time += time;
+            stats.max_tile_time = stats.max_tile_time.max(time);
+            stats.march = stats.march.merge(&march);
+        }
+        let mut remaining = done.iter();
+        self.pending.retain(|_| !remaining.next().unwrap());
 
-            Self::render_chunk(scene, camera, num_pixels, offset, e.1);
-        });
+        stats.tiles_time = start.elapsed();
+        stats.pending_tiles = self.pending.len();
+        stats.average_tile_time /= stats.tiles.max(1) as u32;
+        stats.rays_per_second = stats.march.rays as f64 / stats.tiles_time.as_secs_f64().max(1e-9);
 
-        texture
-            .update(None, img.as_slice(), camera.resolution.x as usize * 4)
-            .map_err(|e| e.to_string())?;
+        self.pass_time += start.elapsed();
+        if !self.pending.is_empty() || self.pass == Pass::Preview {
+            stats.frame_time = frame_start.elapsed();
+            self.stats = stats;
+            return Some(None);
+        }
+
+        let denoise_start = Instant::now();
+        if self.mode == RenderMode::PathTraced && self.denoise && self.aovs.len() == width * height
+        {
+            let frame: Vec<Vec3> = self
+                .accumulation
+                .par_iter()
+                .zip(self.counts.par_iter())
+                .map(|(acc, count)| *acc / (*count).max(1) as f32)
+                .collect();
+            let denoised = self.denoiser.denoise(&frame, &self.aovs, width);
+            for (i, c) in denoised.iter().enumerate() {
+                Self::write_pixel(img, i, c.powf(0.4545));
+            }
+        }
+        stats.denoise_time = denoise_start.elapsed();
+        stats.frame_time = frame_start.elapsed();
+        self.stats = stats;
+
+        Some(Some(std::mem::take(&mut self.pass_time)))
+    }
+
+    fn start_pass(&mut self, pass: Pass, width: usize, height: usize) {
+        self.pass = pass;
+        self.pending = Tile::split(width, height, self.tile_size, self.tile_order);
+        if pass == Pass::Full && self.mode == RenderMode::PathTraced {
+            self.samples += 1;
+        }
+    }
+
+    /// Shades a tile; path traced full passes return raw samples, everything
+    /// else display colors.
+    fn render_tile(&self, scene: &Scene, camera: &Camera, tile: &Tile) -> Vec<Vec3> {
+        if self.mode == RenderMode::Fast
+            && self.pass == Pass::Full
+            && self.packets
+            && (RayMarching { scene }).supports_packets()
+        {
+            return Self::render_tile_packets(scene, camera, tile);
+        }
+
+        let mut rnd = rand::thread_rng();
+        let mut colors = vec![Vec3::ZERO; tile.pixels()];
+
+        let block = match self.pass {
+            Pass::Preview => PREVIEW_BLOCK,
+            Pass::Full => 1,
+        };
+        for y in (0..tile.height).step_by(block) {
+            for x in (0..tile.width).step_by(block) {
+                let coord = vec2((tile.x + x) as f32, (tile.y + y) as f32);
+                let c = match (self.mode, self.pass) {
+                    (RenderMode::Fast, _) => scene.color(camera, coord),
+                    (RenderMode::PathTraced, Pass::Full) => scene.sample(camera, coord, &mut rnd),
+                    (RenderMode::PathTraced, Pass::Preview) => {
+                        scene.sample(camera, coord, &mut rnd).powf(0.4545)
+                    }
+                };
+                for by in y..(y + block).min(tile.height) {
+                    for bx in x..(x + block).min(tile.width) {
+                        colors[by * tile.width + bx] = c;
+                    }
+                }
+            }
+        }
+        colors
+    }
+
+    /// Shades a tile in the fast mode, four pixels of a row at a time.
+    fn render_tile_packets(scene: &Scene, camera: &Camera, tile: &Tile) -> Vec<Vec3> {
+        let mut colors = vec![Vec3::ZERO; tile.pixels()];
+
+        for y in 0..tile.height {
+            let row = &mut colors[y * tile.width..(y + 1) * tile.width];
+            let coord = |x: usize| vec2((tile.x + x) as f32, (tile.y + y) as f32);
+            let mut packets = row.chunks_exact_mut(4);
+            for (i, packet) in packets.by_ref().enumerate() {
+                let coords = std::array::from_fn(|lane| coord(i * 4 + lane));
+                packet.copy_from_slice(&scene.color_packet(camera, coords));
+            }
+            let rest = packets.into_remainder();
+            let start = tile.width - rest.len();
+            for (x, c) in rest.iter_mut().enumerate() {
+                *c = scene.color(camera, coord(start + x));
+            }
+        }
+        colors
+    }
+
+    fn merge_tile(&mut self, tile: Tile, colors: &[Vec3], img: &mut [u8], width: usize) {
+        let accumulate = self.mode == RenderMode::PathTraced && self.pass == Pass::Full;
+        // The denoised image is written once the whole pass is done.
+        let deferred = accumulate && self.denoise;
+
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let i = (tile.y + y) * width + tile.x + x;
+                let mut c = colors[y * tile.width + x];
+                if accumulate {
+                    self.accumulation[i] += c;
+                    self.counts[i] += 1;
+                    c = (self.accumulation[i] / self.counts[i] as f32).powf(0.4545);
+                }
+                if !deferred {
+                    Self::write_pixel(img, i, c);
+                }
+            }
+        }
+    }
+
+    /// Starts a new accumulation after a change, reprojecting the previous
+    /// frames into the new view when temporal reuse is enabled.
+    fn restart(&mut self, scene: &Scene, camera: &Camera) {
+        let width = camera.resolution.x as usize;
+        let num_pixels = width * camera.resolution.y as usize;
+        self.samples = 0;
+
+        let previous_aovs = std::mem::take(&mut self.aovs);
+        self.aovs = (0..num_pixels)
+            .into_par_iter()
+            .map(|i| scene.aov(camera, Self::pixel_center(i, width)))
+            .collect();
+
+        match &self.previous {
+            Some(previous)
+                if self.temporal
+                    && previous.resolution == camera.resolution
+                    && previous_aovs.len() == num_pixels =>
+            {
+                let previous = previous.clone();
+                self.reproject(scene, camera, &previous, &previous_aovs);
+            }
+            _ => {
+                self.accumulation = vec![Vec3::ZERO; num_pixels];
+                self.counts = vec![0; num_pixels];
+            }
+        }
+        self.previous = Some(camera.clone());
+    }
+
+    fn pixel_center(i: usize, width: usize) -> Vec2 {
+        vec2((i % width) as f32 + 0.5, (i / width) as f32 + 0.5)
+    }
+
+    fn reproject(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        previous: &Camera,
+        previous_aovs: &[Aov],
+    ) {
+        let width = camera.resolution.x as usize;
+        let height = camera.resolution.y as usize;
+        let accumulation = &self.accumulation;
+        let counts = &self.counts;
+
+        (self.accumulation, self.counts) = self
+            .aovs
+            .par_iter()
+            .enumerate()
+            .map(|(i, aov)| {
+                if !aov.depth.is_finite() {
+                    return (Vec3::ZERO, 0);
+                }
+                let ray = scene.camera_ray(camera, Self::pixel_center(i, width));
+                let point = ray.origin + ray.direction * aov.depth;
+
+                let Some(coord) = previous.project(point) else {
+                    return (Vec3::ZERO, 0);
+                };
+                let (x, y) = (coord.x.floor(), coord.y.floor());
+                if x < 0. || y < 0. || x >= width as f32 || y >= height as f32 {
+                    return (Vec3::ZERO, 0);
+                }
+                let j = y as usize * width + x as usize;
+
+                // Reject history belonging to another surface.
+                let expected = (point - previous.position).length();
+                let history = &previous_aovs[j];
+                if counts[j] == 0
+                    || (history.depth - expected).abs() > TEMPORAL_DEPTH_TOLERANCE * expected
+                    || history.normal.dot(aov.normal) < TEMPORAL_NORMAL_TOLERANCE
+                {
+                    return (Vec3::ZERO, 0);
+                }
 
-        Ok(())
+                let count = counts[j].min(TEMPORAL_MAX_HISTORY);
+                (accumulation[j] / counts[j] as f32 * count as f32, count)
+            })
+            .unzip();
     }
 }
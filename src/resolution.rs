@@ -0,0 +1,79 @@
+use glam::UVec2;
+
+/// Adjusts the internal render resolution to hold a target frame time.
+#[derive(Debug, Clone)]
+pub struct ResolutionController {
+    pub enabled: bool,
+    /// Render time to aim for, in seconds.
+    pub target_frame_time: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Renders a full resolution frame once nothing changed for `idle_delay` seconds.
+    pub refine_when_idle: bool,
+    pub idle_delay: f32,
+    scale: f32,
+    idle: f32,
+    refined: bool,
+    /// Whether the frame since the last change was already measured.
+    measured: bool,
+}
+
+impl Default for ResolutionController {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target_frame_time: 1. / 30.,
+            min_scale: 0.25,
+            max_scale: 1.,
+            refine_when_idle: true,
+            idle_delay: 0.3,
+            scale: 1.,
+            idle: 0.,
+            refined: false,
+            measured: false,
+        }
+    }
+}
+
+impl ResolutionController {
+    /// Fraction of the window resolution to render at.
+    pub fn scale(&self) -> f32 {
+        if !self.enabled || self.refined {
+            return 1.;
+        }
+        self.scale
+    }
+
+    pub fn resolution(&self, window: UVec2) -> UVec2 {
+        (window.as_vec2() * self.scale())
+            .round()
+            .as_uvec2()
+            .max(UVec2::ONE)
+    }
+
+    /// Records whether the view changed and the time elapsed since the previous call.
+    pub fn update(&mut self, updated: bool, dt: f32) {
+        if updated {
+            self.idle = 0.;
+            self.refined = false;
+            self.measured = false;
+        } else {
+            self.idle += dt;
+            self.refined |= self.refine_when_idle && self.idle > self.idle_delay;
+        }
+    }
+
+    /// Feeds the time spent rendering the last frame, in seconds. Only the
+    /// first frame after a change counts, so that the further samples of an
+    /// unchanged view keep their resolution and accumulate.
+    pub fn frame_rendered(&mut self, render_time: f32) {
+        if !self.enabled || self.refined || self.measured {
+            return;
+        }
+        self.measured = true;
+        // The cost is proportional to the pixel count, that is to scale^2.
+        let ideal = self.scale * (self.target_frame_time / render_time.max(1e-4)).sqrt();
+        self.scale =
+            (self.scale + (ideal - self.scale) * 0.5).clamp(self.min_scale, self.max_scale);
+    }
+}
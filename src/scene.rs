@@ -1,21 +1,80 @@
-use glam::{vec2, UVec2, Vec2, Vec3, Vec4};
+use std::f32::consts::FRAC_1_PI;
+
+use glam::{vec2, BVec4A, Quat, UVec2, Vec2, Vec3, Vec4};
 
 use glam::{vec3, vec4};
 use rand::rngs::ThreadRng;
+use rand::Rng;
 
-use crate::camera::Camera;
+use crate::camera::{Camera, FOCAL_LENGTH};
 use crate::light::{Light, LightSource};
-use crate::ray::Ray;
+use crate::media::Medium;
+use crate::ray::{Ray, RayHit};
+use crate::ray_marching::sdf_tree::SdfNode;
 use crate::ray_marching::RayMarching;
-use crate::utils::materials::{Material, MaterialType};
+use crate::sky::Sky;
+use crate::timeline::Timeline;
+use crate::utils::brdf;
+use crate::utils::environment::power_heuristic;
+use crate::utils::materials::{Material, MaterialType, Pbr, Subsurface};
 use crate::utils::math::{self, pow_vec3};
 use crate::utils::texture::Texture;
+use crate::volume::Volume;
+
+static PATH_MAX_BOUNCES: usize = 6;
+static PATH_MIN_BOUNCES: usize = 3;
+static PATH_EPSILON: f32 = 0.002;
+static SSS_MAX_STEPS: usize = 64;
 
 #[derive(Debug, Clone)]
 pub struct Hit {
     pub dist: f32,
     pub material_index: usize,
     pub color: Vec3,
+    /// Analytic surface normal, when the SDF can provide one cheaply.
+    pub normal: Option<Vec3>,
+}
+
+/// Primary hit attributes used to guide reprojection and denoising.
+#[derive(Debug, Copy, Clone)]
+pub struct Aov {
+    /// Distance along the camera ray, infinite when the sky is seen.
+    pub depth: f32,
+    pub normal: Vec3,
+}
+
+/// Placement of an object, read by the scene's SDF to move it into local space.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: 1.,
+        }
+    }
+}
+
+impl Transform {
+    /// Maps a world space point into the object's local space.
+    pub fn to_local(&self, p: Vec3) -> Vec3 {
+        self.rotation.inverse() * (p - self.translation) / self.scale
+    }
+
+    pub fn to_world(&self, p: Vec3) -> Vec3 {
+        self.rotation * (p * self.scale) + self.translation
+    }
+
+    /// Brings a distance measured in local space back to world space.
+    pub fn to_world_distance(&self, d: f32) -> f32 {
+        d * self.scale
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -24,8 +83,22 @@ pub struct Scene {
     pub textures: Vec<Texture>,
     pub ambient_color: Vec3,
     pub lights: Vec<Light>,
+    pub sky: Sky,
+    pub medium: Option<Medium>,
+    pub volumes: Vec<Volume>,
+    pub max_distance: f32,
+    /// Object placements and free parameters the SDF may read, so they can be
+    /// driven by the timeline.
+    pub transforms: Vec<Transform>,
+    pub params: Vec<f32>,
+    pub timeline: Timeline,
+    /// Node tree evaluated by `Scene::tree_sdf`, for scenes loaded from files.
+    pub tree: Option<SdfNode>,
+    /// Whether `sdf` evaluates `tree`, so rays can be marched in packets.
+    /// Only changed along with `sdf`.
+    evaluates_tree: bool,
 
-    pub sdf: fn(&Scene, &Ray, f32) -> Hit,
+    pub(crate) sdf: fn(&Scene, &Ray, f32) -> Hit,
     pub update: fn(&mut Scene, time: f32) -> bool,
 }
 
@@ -40,6 +113,15 @@ impl Scene {
             textures: vec![],
             ambient_color: Vec3::ZERO,
             lights: vec![],
+            sky: Sky::Gradient,
+            medium: None,
+            volumes: vec![],
+            max_distance: 40.,
+            transforms: vec![],
+            params: vec![],
+            timeline: Timeline::default(),
+            tree: None,
+            evaluates_tree: false,
             sdf,
             update,
         }
@@ -57,28 +139,92 @@ impl Scene {
         s
     }
 
+    /// Makes `tree` the scene's SDF.
+    pub fn set_tree(&mut self, tree: SdfNode) {
+        self.tree = Some(tree);
+        self.sdf = Scene::tree_sdf;
+        self.evaluates_tree = true;
+    }
+
+    /// Whether the SDF is `Scene::tree_sdf`, set by `set_tree`.
+    pub fn evaluates_tree(&self) -> bool {
+        self.evaluates_tree
+    }
+
+    /// SDF function evaluating `Scene::tree`.
+    pub fn tree_sdf(scene: &Scene, ray: &Ray, t: f32) -> Hit {
+        let p = ray.origin + ray.direction * t;
+        let (dist, material_index) = match &scene.tree {
+            Some(tree) => tree.distance(scene, p),
+            None => (f32::MAX, 0),
+        };
+        Hit {
+            dist,
+            material_index,
+            color: scene.materials[material_index].albedo,
+            normal: None,
+        }
+    }
+
+    /// Evaluates the timeline and then the scene's update function at `time`.
+    /// Returns whether the scene changed.
+    pub fn animate(&mut self, time: f32) -> bool {
+        let timeline = std::mem::take(&mut self.timeline);
+        let animated = timeline.evaluate(self, time);
+        self.timeline = timeline;
+        let updated = (self.update)(self, time);
+        animated || updated
+    }
+
     pub fn path_trace(&self, ray: &Ray, l: &Light, res: Vec3, sky: Vec3, bounces: usize) -> Vec3 {
         if bounces > 3 {
             return sky;
         }
+        let hit = RayMarching { scene: self }.march_ray(ray);
+        self.shade(ray, hit, l, res, sky, bounces, None)
+    }
+
+    /// Color seen along `ray`, given where it hit the scene. `shadow` is the
+    /// light's shadow at the hit when it was already traced.
+    #[allow(clippy::too_many_arguments)]
+    fn shade(
+        &self,
+        ray: &Ray,
+        hit: Option<Hit>,
+        l: &Light,
+        res: Vec3,
+        sky: Vec3,
+        bounces: usize,
+        shadow: Option<f32>,
+    ) -> Vec3 {
         let rm = RayMarching { scene: self };
-        if let Some(hit) = rm.march_ray(ray) {
+        if let Some(hit) = hit {
             //res = Vec3::ZERO;
             let p = ray.origin + ray.direction * hit.dist;
-            let n = rm.normal(p);
+            let mat = self.materials[hit.material_index];
+
+            let geo_n = hit.normal.unwrap_or_else(|| rm.normal(p));
+            let n = mat.shading_normal(p, geo_n, &self.textures);
             let refl = math::reflect(ray.direction, n).normalize();
 
-            let mut col = hit.color;
+            if let Some(pbr) = mat.pbr {
+                let mut col =
+                    self.shade_pbr(ray, &rm, p, geo_n, n, &pbr, l, res, sky, bounces, shadow);
+                if let Some(sss) = &mat.subsurface {
+                    col += self.subsurface(&rm, ray, p, n, sss, l);
+                }
+                return self.through_medium(ray, hit.dist, col, sky);
+            }
 
-            let mat = self.materials[hit.material_index];
+            let mut col = hit.color;
 
             if let MaterialType::Reflective { roughness } = mat.kind {
                 if roughness < 1. {
                     let r_ray = &Ray {
-                        origin: p + n * 0.001,
+                        origin: p + geo_n * 0.001,
                         direction: refl,
                     };
-                    let rc = self.path_trace(r_ray, l, res, sky, bounces + 1);
+                    let rc = self.path_trace(r_ray, l, self.sky_along(refl, res), sky, bounces + 1);
                     col = math::mix_vec3(col, rc, roughness);
                 }
             }
@@ -91,13 +237,15 @@ impl Scene {
             let indirect = (0.1 + 0.3 * n.dot((light_dir * vec3(-1.0, 0.0, -1.0)).normalize()))
                 .clamp(0.0, 1.0);
 
-            let shadow = rm.shadow(
-                &Ray {
-                    origin: p + n * 0.0001,
-                    direction: light_dir,
-                },
-                32.,
-            );
+            let shadow = shadow.unwrap_or_else(|| {
+                rm.shadow(
+                    &Ray {
+                        origin: p + geo_n * 0.0001,
+                        direction: light_dir,
+                    },
+                    32.,
+                )
+            });
 
             let half_angle = (-ray.direction - l.direction(p)).normalize();
             let shininess = (n.dot(half_angle)).max(0.).powf(mat.shininess);
@@ -106,51 +254,521 @@ impl Scene {
                 * l.albedo()
                 * math::pow_vec3(Vec3::splat(shadow), vec3(1.3, 1.2, 1.5));
 
-            lightning += sky * occlusion;
+            lightning += self.ambient(n, sky) * occlusion;
             lightning += indirect * l.albedo() * occlusion;
             lightning += mat.specular * shininess * shadow * l.albedo();
 
             col *= lightning * l.intensity();
+            if let Some(sss) = &mat.subsurface {
+                col += self.subsurface(&rm, ray, p, n, sss, l);
+            }
 
-            //col = math::fog(col, hit.dist, ray, 0.2);
+            return self.through_medium(ray, hit.dist, col, sky);
+        }
+        self.through_medium(ray, f32::MAX, res, sky)
+    }
+
+    fn has_media(&self) -> bool {
+        self.medium.is_some() || !self.volumes.is_empty()
+    }
 
-            return col;
+    fn through_medium(&self, ray: &Ray, t: f32, color: Vec3, sky: Vec3) -> Vec3 {
+        if !self.has_media() {
+            return color;
+        }
+        let ambient = self.ambient(Vec3::Y, sky);
+        let mut color = color;
+        for v in &self.volumes {
+            let (transmittance, scattered) = v.integrate(self, ray, t, ambient, 0.5);
+            color = color * transmittance + scattered;
+        }
+        match &self.medium {
+            Some(m) => m.apply(self, ray, t, color, ambient, 0.5),
+            None => color,
         }
-        res
     }
 
-    pub fn color(&self, camera: &Camera, coord: Vec2) -> Vec3 {
+    #[allow(clippy::too_many_arguments)]
+    fn shade_pbr(
+        &self,
+        ray: &Ray,
+        rm: &RayMarching,
+        p: Vec3,
+        geo_n: Vec3,
+        n: Vec3,
+        pbr: &Pbr,
+        l: &Light,
+        res: Vec3,
+        sky: Vec3,
+        bounces: usize,
+        shadow: Option<f32>,
+    ) -> Vec3 {
+        let occlusion = rm.occlusion(p, n);
+        let light_dir = -l.direction(p);
+
+        let shadow = shadow.unwrap_or_else(|| {
+            rm.shadow(
+                &Ray {
+                    origin: p + geo_n * 0.0001,
+                    direction: light_dir,
+                },
+                32.,
+            )
+        });
+
+        let mut col = ray.cook_torrance(&n, &p, l, pbr) * shadow;
+
+        let f = brdf::fresnel_schlick(n.dot(-ray.direction).max(0.), pbr.f0());
+        col += pbr.base_color
+            * (1. - pbr.metallic)
+            * (Vec3::ONE - f)
+            * self.ambient(n, sky)
+            * occlusion;
+
+        // Smooth surfaces pick up the environment, rough ones only the sky.
+        let gloss = 1. - pbr.roughness;
+        let refl = math::reflect(ray.direction, n).normalize();
+        let mut reflected = match self.sky {
+            Sky::Environment(_) => self.sky.specular(refl, pbr.roughness, self.sun_direction()),
+            _ => self.sky_along(refl, sky),
+        };
+        if gloss > 0. {
+            let r_ray = &Ray {
+                origin: p + geo_n * 0.001,
+                direction: refl,
+            };
+            let miss = self.sky_along(refl, res);
+            reflected = math::mix_vec3(
+                reflected,
+                self.path_trace(r_ray, l, miss, sky, bounces + 1),
+                gloss,
+            );
+        }
+        col += f * reflected * occlusion;
+
+        col
+    }
+
+    /// Cheap subsurface term: light bleeds through the thin parts of the solid,
+    /// mostly when looking towards the light.
+    fn subsurface(
+        &self,
+        rm: &RayMarching,
+        ray: &Ray,
+        p: Vec3,
+        n: Vec3,
+        sss: &Subsurface,
+        l: &Light,
+    ) -> Vec3 {
+        let light_dir = -l.direction(p);
+        let transmission = rm.translucency(p, n, sss.radius);
+        let wrap = (n.dot(light_dir) + 1.) * 0.5;
+        let forward = ray.direction.dot(light_dir).max(0.);
+
+        sss.color * transmission * (0.5 * wrap + forward * forward) * l.albedo() * l.intensity()
+    }
+
+    pub fn camera_ray(&self, camera: &Camera, coord: Vec2) -> Ray {
         //let p = (2.0 * coord - camera.resolution) / (1. - camera.resolution.y);
         let ratio = camera.resolution.x / camera.resolution.y;
         let p_ndc = coord / camera.resolution;
 
         let p = vec2((2.0 * p_ndc.x - 1.) * ratio, 1. - 2.0 * p_ndc.y);
 
-        let ray = &Ray {
+        Ray {
             origin: camera.position,
-            direction: (p.x * camera.uu + p.y * camera.vv + 1.5 * camera.ww).normalize(),
-        };
+            direction: (p.x * camera.uu + p.y * camera.vv + FOCAL_LENGTH * camera.ww)
+                .normalize(),
+        }
+    }
 
-        let sky = vec3(0.5, 0.8, 1.) - (0.7 * ray.direction.y).clamp(0.0, 1.0);
+    pub fn sun_direction(&self) -> Vec3 {
+        match self.lights.first() {
+            Some(l) => -l.direction(Vec3::ZERO),
+            None => Vec3::Y,
+        }
+    }
 
-        let sky = math::mix_vec3(
-            sky,
-            vec3(0.5, 0.7, 0.9),
-            (-10.0 * ray.direction.y.max(0.0)).exp(),
-        );
-        let mut res = sky;
+    pub fn sky_color(&self, direction: Vec3) -> Vec3 {
+        self.sky.color(direction, self.sun_direction())
+    }
 
+    /// Sky seen directly from the camera, including the sun.
+    pub fn background(&self, direction: Vec3) -> Vec3 {
+        self.sky.background(direction, self.sun_direction())
+    }
+
+    pub fn ambient(&self, n: Vec3, view_sky: Vec3) -> Vec3 {
+        self.sky.ambient(n, view_sky, self.sun_direction())
+    }
+
+    /// Sky radiance along a secondary direction; the legacy gradient keeps `view_sky`.
+    fn sky_along(&self, direction: Vec3, view_sky: Vec3) -> Vec3 {
+        match self.sky {
+            Sky::Gradient => view_sky,
+            _ => self.sky_color(direction),
+        }
+    }
+
+    pub fn color(&self, camera: &Camera, coord: Vec2) -> Vec3 {
+        let ray = &self.camera_ray(camera, coord);
+        let hit = RayMarching { scene: self }.march_ray(ray);
+        self.primary_color(ray, hit, None)
+    }
+
+    /// Same as `color` for four pixels. Their camera rays, and the shadow rays
+    /// from the surfaces they hit, are marched as packets.
+    pub fn color_packet(&self, camera: &Camera, coords: [Vec2; 4]) -> [Vec3; 4] {
+        let rm = RayMarching { scene: self };
         let l = &self.lights[0];
+        let rays = coords.map(|coord| self.camera_ray(camera, coord));
+        let mut hits = rm.march_packet(&rays);
 
-        let sundot = ray.direction.dot(-l.direction(Vec3::ZERO)).clamp(0.0, 1.0);
+        for (hit, ray) in hits.iter_mut().zip(&rays) {
+            if let Some(hit) = hit.as_mut() {
+                let p = ray.origin + ray.direction * hit.dist;
+                hit.normal.get_or_insert_with(|| rm.normal(p));
+            }
+        }
+        let shadow_rays = std::array::from_fn(|i| match &hits[i] {
+            Some(hit) => {
+                let p = rays[i].origin + rays[i].direction * hit.dist;
+                Ray {
+                    origin: p + hit.normal.unwrap_or(Vec3::Y) * 0.0001,
+                    direction: -l.direction(p),
+                }
+            }
+            None => Ray::new(),
+        });
+        let lit = BVec4A::from(hits.each_ref().map(|h| h.is_some()));
+        let shadows = rm.shadow_packet(&shadow_rays, lit, 32.);
 
-        res += 0.25 * vec3(1.0, 0.7, 0.4) * sundot.powf(5.0);
-        res += 0.25 * vec3(1.0, 0.6, 0.6) * sundot.powf(64.0);
-        res += 0.25 * vec3(1.0, 0.9, 0.6) * sundot.powf(512.0);
+        std::array::from_fn(|i| self.primary_color(&rays[i], hits[i].take(), Some(shadows[i])))
+    }
 
-        res = self.path_trace(ray, l, res, sky, 0);
+    fn primary_color(&self, ray: &Ray, hit: Option<Hit>, shadow: Option<f32>) -> Vec3 {
+        let sky = self.sky_color(ray.direction);
+        let mut res = self.background(ray.direction);
+
+        let l = &self.lights[0];
+
+        res = self.shade(ray, hit, l, res, sky, 0, shadow);
 
         res = res.powf(0.4545);
         res
     }
+
+    pub fn aov(&self, camera: &Camera, coord: Vec2) -> Aov {
+        let ray = self.camera_ray(camera, coord);
+        let rm = RayMarching { scene: self };
+        match rm.march_ray(&ray) {
+            Some(hit) => {
+                let p = ray.origin + ray.direction * hit.dist;
+                Aov {
+                    depth: hit.dist,
+                    normal: hit.normal.unwrap_or_else(|| rm.normal(p)),
+                }
+            }
+            None => Aov {
+                depth: f32::INFINITY,
+                normal: Vec3::ZERO,
+            },
+        }
+    }
+
+    /// One Monte Carlo estimate of the linear radiance arriving at `coord`.
+    pub fn sample(&self, camera: &Camera, coord: Vec2, rnd: &mut ThreadRng) -> Vec3 {
+        let jitter = vec2(rnd.gen(), rnd.gen());
+        let ray = self.camera_ray(camera, coord + jitter);
+        self.trace(&ray, rnd)
+    }
+
+    fn trace(&self, ray: &Ray, rnd: &mut ThreadRng) -> Vec3 {
+        let rm = RayMarching { scene: self };
+        let mut ray = *ray;
+        let mut throughput = Vec3::ONE;
+        let mut radiance = Vec3::ZERO;
+        // Solid angle pdf of the last scattering, None after a specular bounce.
+        let mut last_pdf: Option<f32> = None;
+
+        for bounce in 0..PATH_MAX_BOUNCES {
+            let hit = rm.march_ray(&ray);
+
+            if self.has_media() {
+                let t = hit.as_ref().map_or(f32::MAX, |h| h.dist);
+                let ambient = self.ambient(Vec3::Y, self.sky_color(ray.direction));
+                for v in &self.volumes {
+                    let (transmittance, scattered) = v.integrate(self, &ray, t, ambient, rnd.gen());
+                    radiance += throughput * scattered;
+                    throughput *= transmittance;
+                }
+                if let Some(m) = &self.medium {
+                    let (transmittance, scattered) = m.integrate(self, &ray, t, ambient, rnd.gen());
+                    radiance += throughput * scattered;
+                    throughput *= transmittance;
+                }
+            }
+
+            let Some(hit) = hit else {
+                let sky = if bounce == 0 {
+                    self.background(ray.direction)
+                } else {
+                    self.sky_color(ray.direction)
+                };
+                let weight = match (&self.sky, last_pdf) {
+                    (Sky::Environment(env), Some(pdf)) => {
+                        power_heuristic(pdf, env.pdf(ray.direction))
+                    }
+                    _ => 1.,
+                };
+                radiance += throughput * sky * weight;
+                break;
+            };
+
+            let p = ray.origin + ray.direction * hit.dist;
+            let mat = self.materials[hit.material_index];
+            let geo_n = hit.normal.unwrap_or_else(|| rm.normal(p));
+            let n = mat.shading_normal(p, geo_n, &self.textures);
+            let v = -ray.direction;
+
+            radiance += throughput * hit.color * mat.emission_power;
+
+            // Subsurface materials reflect off the surface with the Fresnel
+            // probability and otherwise enter the solid.
+            let reflectance = match mat.subsurface {
+                Some(_) => brdf::fresnel_schlick(n.dot(v).max(0.), Vec3::splat(0.04)).x,
+                None => 1.,
+            };
+
+            let origin = p + geo_n * PATH_EPSILON;
+            radiance += throughput
+                * self.direct_light(&rm, origin, n, rnd, |l| {
+                    (
+                        Self::evaluate(&mat, hit.color, n, v, l) * reflectance,
+                        Self::pdf(&mat, n, v, l) * reflectance,
+                    )
+                });
+
+            let scattered = match mat.subsurface {
+                Some(sss) if rnd.gen::<f32>() >= reflectance => {
+                    let Some((exit, exit_n, weight)) = Self::random_walk(&rm, &sss, p, geo_n, rnd)
+                    else {
+                        break;
+                    };
+                    throughput *= weight;
+
+                    // Light leaves the solid with a diffuse distribution.
+                    let origin = exit + exit_n * PATH_EPSILON;
+                    radiance += throughput
+                        * self.direct_light(&rm, origin, exit_n, rnd, |l| {
+                            let pdf = exit_n.dot(l).max(0.) * FRAC_1_PI;
+                            (Vec3::splat(pdf), pdf)
+                        });
+                    let direction = brdf::sample_cosine_hemisphere(exit_n, rnd.gen(), rnd.gen());
+                    let pdf = exit_n.dot(direction).max(0.) * FRAC_1_PI;
+                    Some((Ray { origin, direction }, Vec3::ONE, Some(pdf)))
+                }
+                _ => Self::scatter(&rm, &mat, &hit, &ray, p, geo_n, n, rnd)
+                    .map(|(next, weight, pdf)| (next, weight, pdf.map(|pdf| pdf * reflectance))),
+            };
+            let Some((next, weight, pdf)) = scattered else {
+                break;
+            };
+            throughput *= weight;
+            last_pdf = pdf;
+
+            if bounce >= PATH_MIN_BOUNCES {
+                let survive = throughput.max_element().min(0.95);
+                if rnd.gen::<f32>() > survive {
+                    break;
+                }
+                throughput /= survive;
+            }
+            ray = next;
+        }
+        radiance
+    }
+
+    /// Next event estimation towards the lights and the environment map, with
+    /// `bsdf` returning the cosine weighted BSDF and its sampling pdf.
+    fn direct_light(
+        &self,
+        rm: &RayMarching,
+        origin: Vec3,
+        n: Vec3,
+        rnd: &mut ThreadRng,
+        bsdf: impl Fn(Vec3) -> (Vec3, f32),
+    ) -> Vec3 {
+        let mut radiance = Vec3::ZERO;
+        for l in &self.lights {
+            let light_dir = -l.direction(origin);
+            if n.dot(light_dir) <= 0. || !Self::light_visible(rm, origin, l) {
+                continue;
+            }
+            radiance += bsdf(light_dir).0 * l.albedo() * l.intensity();
+        }
+
+        if let Sky::Environment(env) = &self.sky {
+            let (light_dir, pdf) = env.sample_direction(rnd.gen(), rnd.gen());
+            let env_ray = Ray {
+                origin,
+                direction: light_dir,
+            };
+            if pdf > 0. && n.dot(light_dir) > 0. && rm.march_ray(&env_ray).is_none() {
+                let (f, bsdf_pdf) = bsdf(light_dir);
+                radiance += f * env.sample(light_dir) * power_heuristic(pdf, bsdf_pdf) / pdf;
+            }
+        }
+        radiance
+    }
+
+    /// Random walk through a subsurface material, from the entry point to where
+    /// light leaves it again; returns the exit point, its normal and the walk albedo.
+    fn random_walk(
+        rm: &RayMarching,
+        sss: &Subsurface,
+        p: Vec3,
+        geo_n: Vec3,
+        rnd: &mut ThreadRng,
+    ) -> Option<(Vec3, Vec3, Vec3)> {
+        let mut origin = p - geo_n * PATH_EPSILON;
+        let mut direction = brdf::sample_cosine_hemisphere(-geo_n, rnd.gen(), rnd.gen());
+        let mut weight = Vec3::ONE;
+
+        for _ in 0..SSS_MAX_STEPS {
+            let exit = rm.march_ray_inside(&Ray { origin, direction })?;
+            let s = -(1. - rnd.gen::<f32>()).ln() * sss.radius;
+            if s >= exit.dist {
+                let q = origin + direction * exit.dist;
+                return Some((q, rm.normal(q), weight));
+            }
+            origin += direction * s;
+            direction = brdf::sample_uniform_sphere(rnd.gen(), rnd.gen());
+            weight *= sss.color;
+        }
+        None
+    }
+
+    fn light_visible(rm: &RayMarching, origin: Vec3, l: &Light) -> bool {
+        let max_dist = match l {
+            Light::Directional(_) => f32::MAX,
+            _ => l.distance(origin),
+        };
+        let ray = Ray {
+            origin,
+            direction: -l.direction(origin),
+        };
+        match rm.march_ray(&ray) {
+            Some(h) => h.dist > max_dist,
+            None => true,
+        }
+    }
+
+    fn evaluate(mat: &Material, color: Vec3, n: Vec3, v: Vec3, l: Vec3) -> Vec3 {
+        if let Some(pbr) = mat.pbr {
+            return pbr.evaluate(n, v, l);
+        }
+        match mat.kind {
+            MaterialType::Reflective { roughness } => {
+                color * roughness * n.dot(l).max(0.) * FRAC_1_PI
+            }
+            MaterialType::Refractive { .. } => Vec3::ZERO,
+        }
+    }
+
+    fn pdf(mat: &Material, n: Vec3, v: Vec3, l: Vec3) -> f32 {
+        if let Some(pbr) = mat.pbr {
+            return pbr.pdf(n, v, l);
+        }
+        match mat.kind {
+            MaterialType::Reflective { roughness } => roughness * n.dot(l).max(0.) * FRAC_1_PI,
+            MaterialType::Refractive { .. } => 0.,
+        }
+    }
+
+    /// Picks the next path segment, its throughput weight and its pdf, when the
+    /// sampled lobe is not a delta.
+    #[allow(clippy::too_many_arguments)]
+    fn scatter(
+        rm: &RayMarching,
+        mat: &Material,
+        hit: &Hit,
+        ray: &Ray,
+        p: Vec3,
+        geo_n: Vec3,
+        n: Vec3,
+        rnd: &mut ThreadRng,
+    ) -> Option<(Ray, Vec3, Option<f32>)> {
+        let outside = p + geo_n * PATH_EPSILON;
+
+        if let Some(pbr) = mat.pbr {
+            let (direction, weight) = pbr.sample(n, -ray.direction, rnd)?;
+            if direction.dot(geo_n) <= 0. {
+                return None;
+            }
+            return Some((
+                Ray {
+                    origin: outside,
+                    direction,
+                },
+                weight,
+                Some(pbr.pdf(n, -ray.direction, direction)),
+            ));
+        }
+
+        let ray_hit = RayHit {
+            distance: hit.dist,
+            point: outside,
+            normal: n,
+            material_index: hit.material_index,
+        };
+
+        match mat.kind {
+            MaterialType::Reflective { roughness } => {
+                if rnd.gen::<f32>() < roughness {
+                    let direction = brdf::sample_cosine_hemisphere(n, rnd.gen(), rnd.gen());
+                    let next = Ray {
+                        origin: outside,
+                        direction,
+                    };
+                    let pdf = Self::pdf(mat, n, -ray.direction, direction);
+                    return Some((next, hit.color, Some(pdf)));
+                }
+                let next = ray.reflection_ray(ray_hit, roughness, rnd, false, true);
+                Some((next, hit.color, None))
+            }
+            MaterialType::Refractive {
+                transparency,
+                refraction_index,
+                reflectivity,
+            } => {
+                let f = mat.fresnel(ray.direction, n, refraction_index, reflectivity);
+                if rnd.gen::<f32>() < f.max(1. - transparency) {
+                    let next = Ray {
+                        origin: outside,
+                        direction: ray.reflect(n),
+                    };
+                    return Some((next, Vec3::ONE, None));
+                }
+
+                let mut inner = ray.refraction_ray(ray_hit, refraction_index)?;
+                inner.origin = p - geo_n * PATH_EPSILON;
+                let exit = rm.march_ray_inside(&inner)?;
+
+                let q = inner.origin + inner.direction * exit.dist;
+                let exit_n = rm.normal(q);
+                let mut next = inner.refraction_ray(
+                    RayHit {
+                        distance: exit.dist,
+                        point: q,
+                        normal: exit_n,
+                        material_index: exit.material_index,
+                    },
+                    refraction_index,
+                )?;
+                next.origin = q + exit_n * PATH_EPSILON;
+                Some((next, hit.color, None))
+            }
+        }
+    }
 }
@@ -0,0 +1,732 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use glam::{vec3, Quat, Vec3};
+
+use crate::camera::Camera;
+use crate::light::{Directional, Light, Positional, SphericalPositional};
+use crate::mesh::Mesh;
+use crate::mesh::bvh::Aabb;
+use crate::ray_marching::baked::BakeSettings;
+use crate::ray_marching::sdf_tree::{SdfNode, Shape};
+use crate::scene::{Scene, Transform};
+use crate::sky::{Atmosphere, Sky};
+use crate::utils::errors::AppError;
+use crate::utils::image::ImageUtils;
+use crate::utils::materials::{Displacement, Material, MaterialType, NormalMap, Pbr, Subsurface};
+
+static POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Scene described in a text file, one statement per line:
+///
+/// ```text
+/// # comment
+/// max_distance 40
+/// ambient 0.5 0.8 1
+/// sky atmosphere turbidity 2.5
+/// texture ./resources/wood.png
+/// camera 0 1 11  0 0 -1
+/// light directional color 1 0.85 0.7 direction -1 -0.5 -5 intensity 1
+/// material albedo 0.8 0.6 0.4 roughness 0.5 bump 0 0.5 0.01
+/// smooth_union 1
+///     plane 0 1 0 material 0
+///     sphere 0.8 at 0 1.4 7 material 1
+/// end
+/// transform translate 2 1 7 rotate 0 1 0 45
+///     box 0.5 1 0.5 round 0.1 material 1
+/// end
+/// transform translate -2 0 7 scale 0.5
+///     mesh ./resources/bunny.obj grid 64 material 1
+/// end
+/// bake 64 sparse
+///     subtraction
+///         cylinder 0.3 3 at 0 1.5 0
+///         torus 0.3 0.05 at 0 2 0
+///     end
+/// end
+/// ```
+///
+/// Operators (`union`, `smooth_union k`, `intersection`, `subtraction`,
+/// `transform`, `bake resolution`) open a block closed by `end`. Top level
+/// nodes are unioned.
+/// `param v` appends a value to `Scene::params`.
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: Option<Camera>,
+    /// Files read while loading, watched for hot reload.
+    pub dependencies: Vec<String>,
+}
+
+fn no_update(_scene: &mut Scene, _time: f32) -> bool {
+    false
+}
+
+/// Tokens of one line, consumed from the front.
+struct Statement<'a> {
+    path: &'a str,
+    line: usize,
+    tokens: Vec<&'a str>,
+    next: usize,
+}
+
+impl<'a> Statement<'a> {
+    fn error(&self, message: impl AsRef<str>) -> AppError {
+        AppError::ErrorString(format!("{}:{}: {}", self.path, self.line, message.as_ref()))
+    }
+
+    fn word(&mut self) -> Option<&'a str> {
+        let word = self.tokens.get(self.next).copied();
+        self.next += 1;
+        word
+    }
+
+    fn expect_word(&mut self, what: &str) -> Result<&'a str, AppError> {
+        self.word()
+            .ok_or_else(|| self.error(format!("missing {}", what)))
+    }
+
+    fn float(&mut self) -> Result<f32, AppError> {
+        let word = self.expect_word("number")?;
+        word.parse::<f32>()
+            .map_err(|e| self.error(format!("{} '{}'", e, word)))
+    }
+
+    fn index(&mut self) -> Result<usize, AppError> {
+        let word = self.expect_word("index")?;
+        word.parse::<usize>()
+            .map_err(|e| self.error(format!("{} '{}'", e, word)))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, AppError> {
+        Ok(vec3(self.float()?, self.float()?, self.float()?))
+    }
+
+    /// Calls `f` for each `key value..` pair left on the line.
+    fn options(
+        &mut self,
+        mut f: impl FnMut(&str, &mut Self) -> Result<bool, AppError>,
+    ) -> Result<(), AppError> {
+        while let Some(key) = self.word() {
+            if !f(key, self)? {
+                return Err(self.error(format!("unknown option '{}'", key)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Block being filled while parsing the node tree.
+struct Block {
+    operator: Operator,
+    children: Vec<SdfNode>,
+    line: usize,
+}
+
+enum Operator {
+    Root,
+    Union,
+    SmoothUnion(f32),
+    Intersection,
+    Subtraction,
+    Transform(usize),
+    Bake(BakeSettings),
+}
+
+impl Block {
+    fn into_node(self) -> SdfNode {
+        let mut children = self.children;
+        let union = |children: Vec<SdfNode>| {
+            Box::new(
+                Block {
+                    operator: Operator::Union,
+                    children,
+                    line: self.line,
+                }
+                .into_node(),
+            )
+        };
+        match self.operator {
+            Operator::Root | Operator::Union if children.len() == 1 => children.remove(0),
+            Operator::Root | Operator::Union => SdfNode::Union(children),
+            Operator::SmoothUnion(k) => SdfNode::SmoothUnion { k, children },
+            Operator::Intersection => SdfNode::Intersection(children),
+            Operator::Subtraction => SdfNode::Subtraction(children),
+            Operator::Transform(transform) => SdfNode::Transformed {
+                transform,
+                child: union(children),
+            },
+            Operator::Bake(settings) => SdfNode::Baked {
+                settings,
+                volume: None,
+                child: union(children),
+            },
+        }
+    }
+}
+
+impl SceneFile {
+    pub fn load(path: &str) -> Result<SceneFile, AppError> {
+        let text = fs::read_to_string(path)?;
+
+        let mut scene = Scene::new(vec![], Scene::tree_sdf, no_update);
+        let mut camera = None;
+        let mut dependencies = vec![path.to_string()];
+        let mut blocks = vec![Block {
+            operator: Operator::Root,
+            children: vec![],
+            line: 0,
+        }];
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut s = Statement {
+                path,
+                line: n + 1,
+                tokens: line.split_whitespace().collect(),
+                next: 0,
+            };
+
+            let command = s.expect_word("statement")?;
+            match command {
+                "max_distance" => scene.max_distance = s.float()?,
+                "ambient" => scene.ambient_color = s.vec3()?,
+                "sky" => scene.sky = Self::sky(&mut s, &mut dependencies)?,
+                "texture" => {
+                    let file = s.expect_word("texture path")?;
+                    scene.textures.push(ImageUtils::load_image(file)?);
+                    dependencies.push(file.to_string());
+                }
+                "camera" => {
+                    let position = s.vec3()?;
+                    let forward = s.vec3()?;
+                    camera = Some(Camera::new_with_pos(position, forward));
+                }
+                "light" => scene.lights.push(Self::light(&mut s)?),
+                "material" => scene.materials.push(Self::material(&mut s)?),
+                "param" => scene.params.push(s.float()?),
+                "union" | "smooth_union" | "intersection" | "subtraction" | "transform"
+                | "bake" => {
+                    let operator = match command {
+                        "union" => Operator::Union,
+                        "smooth_union" => Operator::SmoothUnion(s.float()?),
+                        "intersection" => Operator::Intersection,
+                        "subtraction" => Operator::Subtraction,
+                        "bake" => Operator::Bake(Self::bake_settings(&mut s)?),
+                        _ => {
+                            scene.transforms.push(Self::transform(&mut s)?);
+                            Operator::Transform(scene.transforms.len() - 1)
+                        }
+                    };
+                    blocks.push(Block {
+                        operator,
+                        children: vec![],
+                        line: n + 1,
+                    });
+                }
+                "end" => {
+                    if blocks.len() == 1 {
+                        return Err(s.error("'end' without an open block"));
+                    }
+                    let block = blocks.pop().unwrap();
+                    if block.children.is_empty() {
+                        return Err(s.error("empty block"));
+                    }
+                    let node = block.into_node();
+                    blocks.last_mut().unwrap().children.push(node);
+                }
+                _ => {
+                    s.next -= 1;
+                    let node = Self::primitive(&mut s, &mut dependencies)?;
+                    blocks.last_mut().unwrap().children.push(node);
+                }
+            }
+
+            if s.next < s.tokens.len() {
+                return Err(s.error(format!("unexpected '{}'", s.tokens[s.next])));
+            }
+        }
+
+        if blocks.len() > 1 {
+            let line = blocks.last().unwrap().line;
+            return Err(AppError::ErrorString(format!(
+                "{}:{}: block is never closed with 'end'",
+                path, line
+            )));
+        }
+        let root = blocks.pop().unwrap();
+        if root.children.is_empty() {
+            return Err(AppError::ErrorString(format!("{}: no geometry", path)));
+        }
+        let mut tree = root.into_node();
+        tree.bake(&scene).map_err(|e| match e {
+            AppError::ErrorString(m) => AppError::ErrorString(format!("{}: {}", path, m)),
+            e => e,
+        })?;
+        scene.set_tree(tree);
+
+        if scene.materials.is_empty() {
+            scene.materials.push(Material::default());
+        }
+        Self::validate(&scene, path)?;
+
+        Ok(SceneFile {
+            scene,
+            camera,
+            dependencies,
+        })
+    }
+
+    fn sky(s: &mut Statement, dependencies: &mut Vec<String>) -> Result<Sky, AppError> {
+        match s.expect_word("sky kind")? {
+            "gradient" => Ok(Sky::Gradient),
+            "constant" => Ok(Sky::Constant(s.vec3()?)),
+            "atmosphere" => {
+                let mut atmosphere = Atmosphere::default();
+                s.options(|key, s| {
+                    match key {
+                        "turbidity" => atmosphere.turbidity = s.float()?,
+                        "exposure" => atmosphere.exposure = s.float()?,
+                        "ground" => atmosphere.ground_albedo = s.vec3()?,
+                        _ => return Ok(false),
+                    }
+                    Ok(true)
+                })?;
+                Ok(Sky::Atmosphere(atmosphere))
+            }
+            "environment" => {
+                let file = s.expect_word("environment path")?;
+                dependencies.push(file.to_string());
+                Ok(Sky::Environment(Arc::new(ImageUtils::load_environment(
+                    file,
+                )?)))
+            }
+            kind => Err(s.error(format!("unknown sky '{}'", kind))),
+        }
+    }
+
+    fn light(s: &mut Statement) -> Result<Light, AppError> {
+        let kind = s.expect_word("light kind")?;
+        let mut albedo = Vec3::ONE;
+        let mut direction = vec3(0., -1., 0.);
+        let mut position = Vec3::ZERO;
+        let mut radius = 0.1;
+        let mut intensity = 1.;
+        s.options(|key, s| {
+            match key {
+                "color" => albedo = s.vec3()?,
+                "direction" => direction = s.vec3()?.normalize(),
+                "position" => position = s.vec3()?,
+                "radius" => radius = s.float()?,
+                "intensity" => intensity = s.float()?,
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+
+        match kind {
+            "directional" => Ok(Light::Directional(Directional {
+                albedo,
+                direction,
+                intensity,
+            })),
+            "point" => Ok(Light::Positional(Positional {
+                albedo,
+                position,
+                intensity,
+            })),
+            "sphere" => Ok(Light::SphericalPositional(SphericalPositional {
+                albedo,
+                position,
+                radius,
+                intensity,
+            })),
+            _ => Err(s.error(format!("unknown light '{}'", kind))),
+        }
+    }
+
+    fn material(s: &mut Statement) -> Result<Material, AppError> {
+        let mut m = Material::default();
+        let mut pbr: Option<Pbr> = None;
+        s.options(|key, s| {
+            match key {
+                "albedo" => m.albedo = s.vec3()?,
+                "ambience" => m.ambience = s.float()?,
+                "diffuse" => m.diffuse = s.float()?,
+                "specular" => m.specular = s.float()?,
+                "shininess" => m.shininess = s.float()?,
+                "texture" => m.texture = Some(s.index()?),
+                "emission" => m.emission_power = s.float()?,
+                "roughness" => {
+                    let roughness = s.float()?;
+                    m.kind = MaterialType::Reflective { roughness };
+                    if let Some(pbr) = pbr.as_mut() {
+                        pbr.roughness = roughness;
+                    }
+                }
+                "glass" => {
+                    m.kind = MaterialType::Refractive {
+                        transparency: s.float()?,
+                        refraction_index: s.float()?,
+                        reflectivity: s.float()?,
+                    }
+                }
+                "metallic" => {
+                    let roughness = match m.kind {
+                        MaterialType::Reflective { roughness } => roughness,
+                        _ => Pbr::default().roughness,
+                    };
+                    pbr = Some(Pbr {
+                        metallic: s.float()?,
+                        roughness,
+                        ..Default::default()
+                    });
+                }
+                "bump" => {
+                    m.normal_map = Some(NormalMap::Bump {
+                        texture: s.index()?,
+                        scale: s.float()?,
+                        strength: s.float()?,
+                    })
+                }
+                "normal_map" => {
+                    m.normal_map = Some(NormalMap::Tangent {
+                        texture: s.index()?,
+                        scale: s.float()?,
+                        strength: s.float()?,
+                    })
+                }
+                "displacement" => {
+                    m.displacement = Some(Displacement {
+                        texture: s.index()?,
+                        scale: s.float()?,
+                        amplitude: s.float()?,
+                        lipschitz: s.float()?,
+                    })
+                }
+                "subsurface" => {
+                    m.subsurface = Some(Subsurface {
+                        color: s.vec3()?,
+                        radius: s.float()?,
+                    })
+                }
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+        m.pbr = pbr.map(|pbr| Pbr {
+            base_color: m.albedo,
+            ..pbr
+        });
+        Ok(m)
+    }
+
+    fn transform(s: &mut Statement) -> Result<Transform, AppError> {
+        let mut transform = Transform::default();
+        s.options(|key, s| {
+            match key {
+                "translate" => transform.translation = s.vec3()?,
+                "rotate" => {
+                    let axis = s.vec3()?.normalize();
+                    transform.rotation = Quat::from_axis_angle(axis, s.float()?.to_radians());
+                }
+                "scale" => transform.scale = s.float()?,
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+        Ok(transform)
+    }
+
+    fn bake_settings(s: &mut Statement) -> Result<BakeSettings, AppError> {
+        let mut settings = BakeSettings {
+            resolution: s.index()? as u32,
+            ..Default::default()
+        };
+        s.options(|key, s| {
+            match key {
+                "sparse" => settings.sparse = true,
+                "nocache" => settings.cache = false,
+                "bounds" => {
+                    settings.bounds = Some(Aabb {
+                        min: s.vec3()?,
+                        max: s.vec3()?,
+                    })
+                }
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+        Ok(settings)
+    }
+
+    fn primitive(s: &mut Statement, dependencies: &mut Vec<String>) -> Result<SdfNode, AppError> {
+        let kind = s.expect_word("statement")?;
+        let mut shape = match kind {
+            "sphere" => Shape::Sphere {
+                center: Vec3::ZERO,
+                radius: s.float()?,
+            },
+            "box" => Shape::Box {
+                center: Vec3::ZERO,
+                half_size: s.vec3()?,
+                corner_radius: 0.,
+            },
+            "plane" => Shape::Plane {
+                point: Vec3::ZERO,
+                normal: s.vec3()?.normalize(),
+            },
+            "cylinder" => Shape::Cylinder {
+                center: Vec3::ZERO,
+                radius: s.float()?,
+                height: s.float()?,
+                corner_radius: 0.,
+            },
+            "capsule" => Shape::Capsule {
+                a: s.vec3()?,
+                b: s.vec3()?,
+                radius: s.float()?,
+            },
+            "torus" => Shape::Torus {
+                center: Vec3::ZERO,
+                major_radius: s.float()?,
+                minor_radius: s.float()?,
+            },
+            "mesh" => {
+                let file = s.expect_word("mesh path")?;
+                dependencies.push(file.to_string());
+                Shape::Mesh(Arc::new(Mesh::load(file)?))
+            }
+            _ => return Err(s.error(format!("unknown statement '{}'", kind))),
+        };
+
+        let mut material = 0;
+        let mut grid = None;
+        s.options(|key, s| {
+            match (key, &mut shape) {
+                ("material", _) => material = s.index()?,
+                ("grid", Shape::Mesh(_)) => grid = Some(s.index()? as u32),
+                (
+                    "at",
+                    Shape::Sphere { center, .. }
+                    | Shape::Box { center, .. }
+                    | Shape::Cylinder { center, .. }
+                    | Shape::Torus { center, .. },
+                ) => *center = s.vec3()?,
+                ("at", Shape::Plane { point, .. }) => *point = s.vec3()?,
+                (
+                    "round",
+                    Shape::Box { corner_radius, .. } | Shape::Cylinder { corner_radius, .. },
+                ) => *corner_radius = s.float()?,
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+
+        if let (Some(resolution), Shape::Mesh(mesh)) = (grid, &shape) {
+            shape = Shape::Grid(Arc::new(mesh.bake(resolution)));
+        }
+        Ok(SdfNode::Primitive { shape, material })
+    }
+
+    /// Checks that the scene is lit and that material and texture indices
+    /// point at loaded entries.
+    fn validate(scene: &Scene, path: &str) -> Result<(), AppError> {
+        if scene.lights.is_empty() {
+            return Err(AppError::ErrorString(format!("{}: no light", path)));
+        }
+
+        let textures = scene.textures.len();
+        for (i, m) in scene.materials.iter().enumerate() {
+            let used = [
+                m.texture,
+                m.normal_map.map(|n| match n {
+                    NormalMap::Bump { texture, .. } | NormalMap::Tangent { texture, .. } => texture,
+                }),
+                m.displacement.map(|d| d.texture),
+            ];
+            if let Some(t) = used.into_iter().flatten().find(|t| *t >= textures) {
+                return Err(AppError::ErrorString(format!(
+                    "{}: material {} uses texture {} but {} are loaded",
+                    path, i, t, textures
+                )));
+            }
+        }
+
+        let mut max_material = 0;
+        scene.tree.as_ref().unwrap().visit(&mut |node| {
+            if let SdfNode::Primitive { material, .. } = node {
+                max_material = max_material.max(*material);
+            }
+        });
+        if max_material >= scene.materials.len() {
+            return Err(AppError::ErrorString(format!(
+                "{}: material {} is used but {} are defined",
+                path,
+                max_material,
+                scene.materials.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Polls the modification times of a scene file and its dependencies.
+pub struct SceneWatcher {
+    pub path: String,
+    pub interval: Duration,
+    files: Vec<(String, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl SceneWatcher {
+    pub fn new(path: &str, dependencies: &[String]) -> SceneWatcher {
+        let mut watcher = SceneWatcher {
+            path: path.to_string(),
+            interval: POLL_INTERVAL,
+            files: vec![],
+            last_poll: Instant::now(),
+        };
+        watcher.watch(dependencies);
+        watcher
+    }
+
+    /// Replaces the watched files, remembering their current state.
+    pub fn watch(&mut self, dependencies: &[String]) {
+        self.files.clear();
+        for file in std::iter::once(&self.path).chain(dependencies) {
+            if !self.files.iter().any(|(f, _)| f == file) {
+                self.files.push((file.clone(), modified(file)));
+            }
+        }
+    }
+
+    /// Returns true when a watched file changed since the last call. Checks
+    /// the disk at most once per `interval`.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < self.interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (file, time) in self.files.iter_mut() {
+            let now = modified(file);
+            if now != *time {
+                *time = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static LIGHT: &str = "light directional direction 0 -1 0\n";
+
+    /// Loads `text` from a temporary file, returning the error message
+    /// without the file path.
+    fn load(name: &str, text: &str) -> Result<SceneFile, String> {
+        let path = std::env::temp_dir().join(format!("scene_{}_{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let path = path.to_str().unwrap();
+        SceneFile::load(path).map_err(|e| match e {
+            AppError::ErrorString(m) => m.strip_prefix(path).unwrap_or(&m).to_string(),
+            e => format!("{:?}", e),
+        })
+    }
+
+    fn error(name: &str, text: &str) -> String {
+        match load(name, text) {
+            Ok(_) => panic!("{} loaded without error", name),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn loads_tree_and_settings() {
+        let file = load(
+            "valid.scene",
+            &format!(
+                "{LIGHT}camera 0 1 5  0 0 -1\nmaterial albedo 1 0 0\n\
+                 union\n    sphere 1 at 0 1 0\n    box 1 1 1 round 0.1 material 0\nend\n"
+            ),
+        )
+        .unwrap();
+        assert!(file.camera.is_some());
+        assert_eq!(file.scene.lights.len(), 1);
+        assert!(file.scene.evaluates_tree());
+        assert!(matches!(file.scene.tree, Some(SdfNode::Union(ref c)) if c.len() == 2));
+    }
+
+    #[test]
+    fn rejects_unclosed_block() {
+        let text = format!("{LIGHT}union\n    sphere 1\n");
+        assert_eq!(
+            error("unclosed.scene", &text),
+            ":2: block is never closed with 'end'"
+        );
+    }
+
+    #[test]
+    fn rejects_end_without_block() {
+        let text = format!("{LIGHT}sphere 1\nend\n");
+        assert_eq!(error("end.scene", &text), ":3: 'end' without an open block");
+    }
+
+    #[test]
+    fn rejects_empty_block() {
+        let text = format!("{LIGHT}sphere 1\n\n# nothing inside\nintersection\nend\n");
+        assert_eq!(error("empty.scene", &text), ":6: empty block");
+    }
+
+    #[test]
+    fn rejects_unknown_option() {
+        let text = format!("{LIGHT}sphere 1 colour 1 0 0\n");
+        assert_eq!(error("option.scene", &text), ":2: unknown option 'colour'");
+    }
+
+    #[test]
+    fn rejects_trailing_tokens() {
+        let text = format!("{LIGHT}max_distance 10 20\nsphere 1\n");
+        assert_eq!(error("trailing.scene", &text), ":2: unexpected '20'");
+    }
+
+    #[test]
+    fn rejects_missing_material() {
+        let text = format!("{LIGHT}material albedo 1 1 1\nsphere 1 material 2\n");
+        assert_eq!(
+            error("material.scene", &text),
+            ": material 2 is used but 1 are defined"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_texture() {
+        let text = format!("{LIGHT}material albedo 1 1 1 bump 0 1 0.1\nsphere 1\n");
+        assert_eq!(
+            error("texture.scene", &text),
+            ": material 0 uses texture 0 but 0 are loaded"
+        );
+    }
+
+    #[test]
+    fn rejects_unlit_scene() {
+        assert_eq!(error("unlit.scene", "sphere 1\n"), ": no light");
+    }
+
+    #[test]
+    fn rejects_empty_scene() {
+        assert_eq!(error("nothing.scene", LIGHT), ": no geometry");
+    }
+}
@@ -0,0 +1,194 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+
+use crate::utils::brdf;
+use crate::utils::environment::EnvironmentMap;
+use crate::utils::math;
+
+static IRRADIANCE_SAMPLES: usize = 16;
+static GOLDEN_RATIO: f32 = 0.618_034;
+
+/// Preetham et al. analytic daylight model.
+#[derive(Debug, Copy, Clone)]
+pub struct Atmosphere {
+    /// Haziness, from about 2 (clear) to 10 (hazy).
+    pub turbidity: f32,
+    /// Scale applied to the model luminance (kcd/m2).
+    pub exposure: f32,
+    pub ground_albedo: Vec3,
+    /// Angular radius of the sun disc, in radians.
+    pub sun_radius: f32,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self {
+            turbidity: 2.5,
+            exposure: 0.06,
+            ground_albedo: vec3(0.3, 0.28, 0.25),
+            sun_radius: 0.0093,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Sky {
+    /// Hand tuned blue gradient with a glow around the sun. Ambient light is
+    /// taken from the viewing direction, as the scenes were tuned with it.
+    Gradient,
+    Constant(Vec3),
+    Atmosphere(Atmosphere),
+    Environment(Arc<EnvironmentMap>),
+}
+
+fn perez(cos_theta: f32, gamma: f32, c: [f32; 5]) -> f32 {
+    (1. + c[0] * (c[1] / cos_theta.max(0.01)).exp())
+        * (1. + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+}
+
+impl Atmosphere {
+    pub fn color(&self, direction: Vec3, sun: Vec3) -> Vec3 {
+        let t = self.turbidity;
+        // The model is only defined for a sun above the horizon.
+        let sun_elevation = sun.y.clamp(0.01, 1.);
+        let theta_s = sun_elevation.acos();
+
+        let cy = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let cx = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let cyy = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let th = theta_s;
+        let th2 = th * th;
+        let th3 = th2 * th;
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let up = vec3(direction.x, direction.y.max(0.001), direction.z).normalize();
+        let gamma = up.dot(sun).clamp(-1., 1.).acos();
+
+        let luminance = zenith_luminance * perez(up.y, gamma, cy) / perez(1., theta_s, cy);
+        let x = zenith_x * perez(up.y, gamma, cx) / perez(1., theta_s, cx);
+        let y = zenith_y * perez(up.y, gamma, cyy) / perez(1., theta_s, cyy);
+
+        let xyz = vec3(x / y * luminance, luminance, (1. - x - y) / y * luminance);
+        let rgb = vec3(
+            3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+            -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+            0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+        )
+        .max(Vec3::ZERO)
+            * self.exposure
+            * math::smooth_step(-0.1, 0.05, sun.y);
+
+        let ground = self.ground_albedo * rgb.y.max(0.) * sun_elevation;
+        math::mix_vec3(rgb, ground, math::smooth_step(0., -0.05, direction.y))
+    }
+
+    pub fn sun_color(&self, direction: Vec3, sun: Vec3) -> Vec3 {
+        let disc = math::smooth_step(
+            (self.sun_radius * 1.5).cos(),
+            self.sun_radius.cos(),
+            direction.dot(sun),
+        );
+        let horizon = math::smooth_step(-0.02, 0.02, sun.y);
+        vec3(1.0, 0.9, 0.75) * disc * horizon * 20.
+    }
+}
+
+impl Sky {
+    /// Radiance arriving from `direction`, excluding the sun disc.
+    pub fn color(&self, direction: Vec3, sun: Vec3) -> Vec3 {
+        match self {
+            Sky::Gradient => {
+                let sky = vec3(0.5, 0.8, 1.) - (0.7 * direction.y).clamp(0.0, 1.0);
+
+                math::mix_vec3(
+                    sky,
+                    vec3(0.5, 0.7, 0.9),
+                    (-10.0 * direction.y.max(0.0)).exp(),
+                )
+            }
+            Sky::Constant(c) => *c,
+            Sky::Atmosphere(a) => a.color(direction, sun),
+            Sky::Environment(env) => env.sample(direction),
+        }
+    }
+
+    /// Sky seen directly from the camera, including the sun.
+    pub fn background(&self, direction: Vec3, sun: Vec3) -> Vec3 {
+        let mut res = self.color(direction, sun);
+        match self {
+            Sky::Gradient => {
+                let sundot = direction.dot(sun).clamp(0.0, 1.0);
+
+                res += 0.25 * vec3(1.0, 0.7, 0.4) * sundot.powf(5.0);
+                res += 0.25 * vec3(1.0, 0.6, 0.6) * sundot.powf(64.0);
+                res += 0.25 * vec3(1.0, 0.9, 0.6) * sundot.powf(512.0);
+            }
+            Sky::Atmosphere(a) => res += a.sun_color(direction, sun),
+            Sky::Constant(_) | Sky::Environment(_) => {}
+        }
+        res
+    }
+
+    /// Cosine weighted average of the sky radiance around `n`; multiplied by
+    /// a diffuse albedo it gives the light reflected from the sky.
+    pub fn irradiance(&self, n: Vec3, sun: Vec3) -> Vec3 {
+        match self {
+            Sky::Constant(c) => return *c,
+            Sky::Environment(env) => return env.irradiance(n),
+            _ => {}
+        }
+        let mut sum = Vec3::ZERO;
+        for i in 0..IRRADIANCE_SAMPLES {
+            let u1 = (i as f32 + 0.5) / IRRADIANCE_SAMPLES as f32;
+            let u2 = (i as f32 * GOLDEN_RATIO).fract();
+            sum += self.color(brdf::sample_cosine_hemisphere(n, u1, u2), sun);
+        }
+        sum / IRRADIANCE_SAMPLES as f32
+    }
+
+    /// Sky radiance along `direction` as seen by a surface of the given roughness.
+    pub fn specular(&self, direction: Vec3, roughness: f32, sun: Vec3) -> Vec3 {
+        match self {
+            Sky::Environment(env) => env.specular(direction, roughness),
+            _ => self.color(direction, sun),
+        }
+    }
+
+    /// Ambient light for a surface facing `n`. `view_sky` is the sky behind
+    /// the shaded point, which the legacy gradient uses instead of `n`.
+    pub fn ambient(&self, n: Vec3, view_sky: Vec3, sun: Vec3) -> Vec3 {
+        match self {
+            Sky::Gradient => view_sky,
+            _ => self.irradiance(n, sun),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::f32::consts::PI;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Rows of tiles from the top left corner.
+    Scanline,
+    /// Rings of tiles spiralling out of the image center, so the middle of the
+    /// view resolves first.
+    Spiral,
+}
+
+/// Rectangle of pixels rendered as one unit of work.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    /// Covers a `width` x `height` image with square tiles of `size` pixels,
+    /// trimmed at the right and bottom edges.
+    pub fn split(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+        let size = size.max(1);
+        let mut tiles = vec![];
+        for y in (0..height).step_by(size) {
+            for x in (0..width).step_by(size) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                });
+            }
+        }
+
+        if order == TileOrder::Spiral {
+            let cx = width as f32 * 0.5;
+            let cy = height as f32 * 0.5;
+            let key = |t: &Tile| {
+                let dx = (t.x as f32 + t.width as f32 * 0.5 - cx) / size as f32;
+                let dy = (t.y as f32 + t.height as f32 * 0.5 - cy) / size as f32;
+                let ring = dx.abs().max(dy.abs()).round();
+                let angle = dy.atan2(dx) + PI;
+                (ring, angle)
+            };
+            tiles.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        tiles
+    }
+
+    pub fn pixels(&self) -> usize {
+        self.width * self.height
+    }
+}
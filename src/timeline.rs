@@ -0,0 +1,323 @@
+use glam::{Quat, Vec3};
+
+use crate::light::Light;
+use crate::scene::Scene;
+use crate::utils::materials::MaterialType;
+
+/// Shapes the progress between two keyframes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// Holds the previous value until the next keyframe.
+    Step,
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Step => 0.,
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1. - (1. - t).powi(3),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// Values that can be keyframed.
+pub trait Animatable: Copy {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn interpolate(a: f32, b: f32, t: f32) -> f32 {
+        a + (b - a) * t
+    }
+}
+
+impl Animatable for Vec3 {
+    fn interpolate(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+        a.lerp(b, t)
+    }
+}
+
+impl Animatable for Quat {
+    fn interpolate(a: Quat, b: Quat, t: f32) -> Quat {
+        a.slerp(b, t)
+    }
+}
+
+/// Value at a point in time. The easing shapes the segment towards the next
+/// keyframe.
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    pub easing: Easing,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Track<T> {
+    pub keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new() -> Track<T> {
+        Track { keyframes: vec![] }
+    }
+
+    /// Adds a keyframe, keeping them ordered by time.
+    pub fn key(mut self, time: f32, value: T, easing: Easing) -> Track<T> {
+        let i = self.keyframes.partition_point(|k| k.time <= time);
+        self.keyframes.insert(
+            i,
+            Keyframe {
+                time,
+                value,
+                easing,
+            },
+        );
+        self
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |k| k.time)
+    }
+
+    /// Value at `time`, held constant before the first and after the last
+    /// keyframe.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let keys = &self.keyframes;
+        let i = keys.partition_point(|k| k.time <= time);
+        if i == 0 {
+            return keys.first().map(|k| k.value);
+        }
+        if i == keys.len() {
+            return keys.last().map(|k| k.value);
+        }
+
+        let k1 = &keys[i - 1];
+        let k2 = &keys[i];
+        let span = k2.time - k1.time;
+        let t = if span > 0. {
+            (time - k1.time) / span
+        } else {
+            1.
+        };
+        Some(T::interpolate(k1.value, k2.value, k1.easing.apply(t)))
+    }
+}
+
+/// A track bound to the scene property it drives.
+#[derive(Debug, Clone)]
+pub enum Animation {
+    LightIntensity {
+        light: usize,
+        track: Track<f32>,
+    },
+    LightColor {
+        light: usize,
+        track: Track<Vec3>,
+    },
+    /// Direction of a directional light, renormalized after interpolation.
+    LightDirection {
+        light: usize,
+        track: Track<Vec3>,
+    },
+    LightPosition {
+        light: usize,
+        track: Track<Vec3>,
+    },
+    MaterialAlbedo {
+        material: usize,
+        track: Track<Vec3>,
+    },
+    /// Roughness of the PBR model when the material has one, otherwise of a
+    /// reflective material.
+    MaterialRoughness {
+        material: usize,
+        track: Track<f32>,
+    },
+    MaterialEmission {
+        material: usize,
+        track: Track<f32>,
+    },
+    Translation {
+        transform: usize,
+        track: Track<Vec3>,
+    },
+    Rotation {
+        transform: usize,
+        track: Track<Quat>,
+    },
+    Scale {
+        transform: usize,
+        track: Track<f32>,
+    },
+    /// Free SDF parameter, read by the scene's SDF from `Scene::params`.
+    Param {
+        index: usize,
+        track: Track<f32>,
+    },
+}
+
+impl Animation {
+    pub fn duration(&self) -> f32 {
+        match self {
+            Animation::LightIntensity { track, .. }
+            | Animation::MaterialRoughness { track, .. }
+            | Animation::MaterialEmission { track, .. }
+            | Animation::Scale { track, .. }
+            | Animation::Param { track, .. } => track.duration(),
+            Animation::LightColor { track, .. }
+            | Animation::LightDirection { track, .. }
+            | Animation::LightPosition { track, .. }
+            | Animation::MaterialAlbedo { track, .. }
+            | Animation::Translation { track, .. } => track.duration(),
+            Animation::Rotation { track, .. } => track.duration(),
+        }
+    }
+
+    /// Writes the value at `time` into the scene. Targets that do not exist,
+    /// or lights without the animated property, are left untouched.
+    pub fn apply(&self, scene: &mut Scene, time: f32) {
+        match self {
+            Animation::LightIntensity { light, track } => {
+                if let (Some(l), Some(v)) = (scene.lights.get_mut(*light), track.sample(time)) {
+                    match l {
+                        Light::Directional(l) => l.intensity = v,
+                        Light::Positional(l) => l.intensity = v,
+                        Light::SphericalPositional(l) => l.intensity = v,
+                    }
+                }
+            }
+            Animation::LightColor { light, track } => {
+                if let (Some(l), Some(v)) = (scene.lights.get_mut(*light), track.sample(time)) {
+                    match l {
+                        Light::Directional(l) => l.albedo = v,
+                        Light::Positional(l) => l.albedo = v,
+                        Light::SphericalPositional(l) => l.albedo = v,
+                    }
+                }
+            }
+            Animation::LightDirection { light, track } => {
+                if let (Some(Light::Directional(l)), Some(v)) =
+                    (scene.lights.get_mut(*light), track.sample(time))
+                {
+                    l.direction = v.normalize();
+                }
+            }
+            Animation::LightPosition { light, track } => {
+                if let (Some(l), Some(v)) = (scene.lights.get_mut(*light), track.sample(time)) {
+                    match l {
+                        Light::Directional(_) => {}
+                        Light::Positional(l) => l.position = v,
+                        Light::SphericalPositional(l) => l.position = v,
+                    }
+                }
+            }
+            Animation::MaterialAlbedo { material, track } => {
+                if let (Some(m), Some(v)) = (scene.materials.get_mut(*material), track.sample(time))
+                {
+                    m.albedo = v;
+                    if let Some(pbr) = m.pbr.as_mut() {
+                        pbr.base_color = v;
+                    }
+                }
+            }
+            Animation::MaterialRoughness { material, track } => {
+                if let (Some(m), Some(v)) = (scene.materials.get_mut(*material), track.sample(time))
+                {
+                    if let Some(pbr) = m.pbr.as_mut() {
+                        pbr.roughness = v;
+                    } else if let MaterialType::Reflective { roughness } = &mut m.kind {
+                        *roughness = v;
+                    }
+                }
+            }
+            Animation::MaterialEmission { material, track } => {
+                if let (Some(m), Some(v)) = (scene.materials.get_mut(*material), track.sample(time))
+                {
+                    m.emission_power = v;
+                }
+            }
+            Animation::Translation { transform, track } => {
+                if let (Some(tr), Some(v)) =
+                    (scene.transforms.get_mut(*transform), track.sample(time))
+                {
+                    tr.translation = v;
+                }
+            }
+            Animation::Rotation { transform, track } => {
+                if let (Some(tr), Some(v)) =
+                    (scene.transforms.get_mut(*transform), track.sample(time))
+                {
+                    tr.rotation = v.normalize();
+                }
+            }
+            Animation::Scale { transform, track } => {
+                if let (Some(tr), Some(v)) =
+                    (scene.transforms.get_mut(*transform), track.sample(time))
+                {
+                    tr.scale = v;
+                }
+            }
+            Animation::Param { index, track } => {
+                if let (Some(p), Some(v)) = (scene.params.get_mut(*index), track.sample(time)) {
+                    *p = v;
+                }
+            }
+        }
+    }
+}
+
+/// Set of animations evaluated purely from time, so a frame rendered offline
+/// matches the one seen interactively at the same time.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    pub animations: Vec<Animation>,
+    pub looping: bool,
+}
+
+impl Timeline {
+    pub fn add(&mut self, animation: Animation) {
+        self.animations.push(animation);
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.animations
+            .iter()
+            .map(|a| a.duration())
+            .fold(0., f32::max)
+    }
+
+    /// Time inside the timeline, wrapped when looping.
+    pub fn local_time(&self, time: f32) -> f32 {
+        let duration = self.duration();
+        if self.looping && duration > 0. {
+            time.rem_euclid(duration)
+        } else {
+            time
+        }
+    }
+
+    /// Applies every animation at `time`. Returns whether `time` is still
+    /// within the keyframed range, where the scene keeps changing; a timeline
+    /// that ended without looping holds its last values.
+    pub fn evaluate(&self, scene: &mut Scene, time: f32) -> bool {
+        if self.animations.is_empty() {
+            return false;
+        }
+        let local = self.local_time(time);
+        for animation in &self.animations {
+            animation.apply(scene, local);
+        }
+        let duration = self.duration();
+        duration > 0. && (self.looping || time <= duration)
+    }
+}
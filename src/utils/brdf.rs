@@ -0,0 +1,152 @@
+use std::f32::consts::PI;
+
+use glam::Vec3;
+use rand::Rng;
+
+use super::materials::Pbr;
+use super::math;
+
+static MIN_ALPHA: f32 = 0.002;
+static CLEARCOAT_F0: f32 = 0.04;
+
+pub fn fresnel_schlick(cos_theta: f32, f0: Vec3) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1. - cos_theta).clamp(0., 1.).powi(5)
+}
+
+pub fn ggx_distribution(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.) + 1.;
+    a2 / (PI * d * d)
+}
+
+/// Height correlated Smith term, already divided by `4 n.l n.v`.
+pub fn smith_ggx_visibility(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let gv = n_dot_l * (n_dot_v * n_dot_v * (1. - a2) + a2).sqrt();
+    let gl = n_dot_v * (n_dot_l * n_dot_l * (1. - a2) + a2).sqrt();
+    0.5 / (gv + gl).max(1e-7)
+}
+
+pub fn sample_cosine_hemisphere(n: Vec3, u1: f32, u2: f32) -> Vec3 {
+    let (t, b) = n.any_orthonormal_pair();
+    let r = u1.sqrt();
+    let phi = 2. * PI * u2;
+    (t * r * phi.cos() + b * r * phi.sin() + n * (1. - u1).max(0.).sqrt()).normalize()
+}
+
+pub fn sample_uniform_sphere(u1: f32, u2: f32) -> Vec3 {
+    let z = 1. - 2. * u1;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Samples a GGX distributed half vector around `n`.
+pub fn sample_ggx_half(n: Vec3, alpha: f32, u1: f32, u2: f32) -> Vec3 {
+    let (t, b) = n.any_orthonormal_pair();
+    let a2 = alpha * alpha;
+    let phi = 2. * PI * u1;
+    let cos_theta = ((1. - u2) / (1. + (a2 - 1.) * u2)).sqrt();
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    (t * sin_theta * phi.cos() + b * sin_theta * phi.sin() + n * cos_theta).normalize()
+}
+
+fn ggx_pdf(n_dot_h: f32, v_dot_h: f32, alpha: f32) -> f32 {
+    ggx_distribution(n_dot_h, alpha) * n_dot_h / (4. * v_dot_h).max(1e-7)
+}
+
+impl Pbr {
+    fn alpha(&self) -> f32 {
+        (self.roughness * self.roughness).max(MIN_ALPHA)
+    }
+
+    fn clearcoat_alpha(&self) -> f32 {
+        (self.clearcoat_roughness * self.clearcoat_roughness).max(MIN_ALPHA)
+    }
+
+    pub fn f0(&self) -> Vec3 {
+        let r = (self.ior - 1.) / (self.ior + 1.);
+        let dielectric = Vec3::splat(r * r * self.specular);
+        dielectric.lerp(self.base_color, self.metallic)
+    }
+
+    /// Cook-Torrance GGX BRDF multiplied by the cosine term, for light
+    /// arriving from `l` and leaving towards `v`.
+    pub fn evaluate(&self, n: Vec3, v: Vec3, l: Vec3) -> Vec3 {
+        let n_dot_l = n.dot(l);
+        let n_dot_v = n.dot(v);
+        if n_dot_l <= 0. || n_dot_v <= 0. {
+            return Vec3::ZERO;
+        }
+        let h = (v + l).normalize();
+        let n_dot_h = n.dot(h).max(0.);
+        let v_dot_h = v.dot(h).max(0.);
+
+        let alpha = self.alpha();
+        let f = fresnel_schlick(v_dot_h, self.f0());
+        let specular =
+            f * ggx_distribution(n_dot_h, alpha) * smith_ggx_visibility(n_dot_v, n_dot_l, alpha);
+        let diffuse = (Vec3::ONE - f) * (1. - self.metallic) * self.base_color / PI;
+
+        let mut brdf = diffuse + specular;
+
+        if self.clearcoat > 0. {
+            let alpha = self.clearcoat_alpha();
+            let fc = fresnel_schlick(v_dot_h, Vec3::splat(CLEARCOAT_F0)).x * self.clearcoat;
+            let coat = fc
+                * ggx_distribution(n_dot_h, alpha)
+                * smith_ggx_visibility(n_dot_v, n_dot_l, alpha);
+            brdf = brdf * (1. - fc) + Vec3::splat(coat);
+        }
+
+        brdf * n_dot_l
+    }
+
+    /// Lobe selection probabilities (diffuse, specular, clearcoat).
+    fn lobe_weights(&self) -> (f32, f32, f32) {
+        let coat = self.clearcoat * 0.25;
+        let specular = (0.5 + 0.5 * self.metallic) * (1. - coat);
+        (1. - specular - coat, specular, coat)
+    }
+
+    pub fn pdf(&self, n: Vec3, v: Vec3, l: Vec3) -> f32 {
+        let n_dot_l = n.dot(l);
+        if n_dot_l <= 0. {
+            return 0.;
+        }
+        let h = (v + l).normalize();
+        let n_dot_h = n.dot(h).max(0.);
+        let v_dot_h = v.dot(h).max(0.);
+        let (pd, ps, pc) = self.lobe_weights();
+
+        pd * n_dot_l / PI
+            + ps * ggx_pdf(n_dot_h, v_dot_h, self.alpha())
+            + pc * ggx_pdf(n_dot_h, v_dot_h, self.clearcoat_alpha())
+    }
+
+    /// Importance samples an incoming direction for the outgoing direction `v`.
+    /// Returns the direction and the sample weight `f * cos / pdf`.
+    pub fn sample(&self, n: Vec3, v: Vec3, rnd: &mut impl Rng) -> Option<(Vec3, Vec3)> {
+        let (pd, ps, _) = self.lobe_weights();
+        let lobe: f32 = rnd.gen();
+        let (u1, u2): (f32, f32) = (rnd.gen(), rnd.gen());
+
+        let l = if lobe < pd {
+            sample_cosine_hemisphere(n, u1, u2)
+        } else {
+            let alpha = if lobe < pd + ps {
+                self.alpha()
+            } else {
+                self.clearcoat_alpha()
+            };
+            let h = sample_ggx_half(n, alpha, u1, u2);
+            math::reflect(-v, h)
+        };
+
+        let pdf = self.pdf(n, v, l);
+        if pdf <= 0. {
+            return None;
+        }
+        Some((l, self.evaluate(n, v, l) / pdf))
+    }
+}
@@ -0,0 +1,258 @@
+use std::f32::consts::PI;
+
+use glam::{vec3, Vec3};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::brdf;
+use super::math;
+
+static SPECULAR_LEVELS: usize = 5;
+static SPECULAR_SAMPLES: usize = 64;
+static MIN_LEVEL_WIDTH: u32 = 16;
+
+/// Equirectangular HDR image, indexed by direction.
+#[derive(Default, Debug, Clone)]
+pub struct EnvironmentMap {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec3>,
+    /// Order 2 spherical harmonics of the radiance, for diffuse lighting.
+    pub irradiance_sh: [Vec3; 9],
+    /// Copies of the map prefiltered for increasing GGX roughness; the map
+    /// itself is roughness 0.
+    pub specular_mips: Vec<EnvironmentMap>,
+    marginal_cdf: Vec<f32>,
+    conditional_cdf: Vec<f32>,
+    total_weight: f32,
+}
+
+fn sh_basis(d: Vec3) -> [f32; 9] {
+    [
+        0.282_095,
+        0.488_603 * d.y,
+        0.488_603 * d.z,
+        0.488_603 * d.x,
+        1.092_548 * d.x * d.y,
+        1.092_548 * d.y * d.z,
+        0.315_392 * (3. * d.z * d.z - 1.),
+        1.092_548 * d.x * d.z,
+        0.546_274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+fn hammersley(i: usize, n: usize) -> (f32, f32) {
+    (
+        (i as f32 + 0.5) / n as f32,
+        (i as u32).reverse_bits() as f32 / 4_294_967_296.,
+    )
+}
+
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0. {
+        return 0.;
+    }
+    a / (a + b)
+}
+
+impl EnvironmentMap {
+    /// Builds a map and precomputes its irradiance, specular mips and sampling tables.
+    pub fn new(path: String, width: u32, height: u32, pixels: Vec<Vec3>) -> EnvironmentMap {
+        let mut env = EnvironmentMap {
+            path,
+            width,
+            height,
+            pixels,
+            ..Default::default()
+        };
+        env.irradiance_sh = env.project_sh();
+        env.specular_mips = env.prefilter_specular();
+        env.build_distribution();
+        env
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn from_uv(&self, u: f32, v: f32) -> Vec3 {
+        let x = (u - u.floor()) * self.width as f32 - 0.5;
+        let y = (v.clamp(0., 1.) * self.height as f32 - 0.5).clamp(0., (self.height - 1) as f32);
+
+        let x0 = x.floor();
+        let y0 = y.floor() as u32;
+        let fx = x - x0;
+        let fy = y - y0 as f32;
+        let x0 = (x0 as i32).rem_euclid(self.width as i32) as u32;
+        let x1 = (x0 + 1) % self.width;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let top = self.pixel(x0, y0).lerp(self.pixel(x1, y0), fx);
+        let bottom = self.pixel(x0, y1).lerp(self.pixel(x1, y1), fx);
+        top.lerp(bottom, fy)
+    }
+
+    pub fn direction_to_uv(direction: Vec3) -> (f32, f32) {
+        let u = 0.5 + direction.z.atan2(direction.x) / (2. * PI);
+        let v = direction.y.clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    pub fn uv_to_direction(u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2. * PI;
+        let theta = v * PI;
+        vec3(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        let (u, v) = Self::direction_to_uv(direction);
+        self.from_uv(u, v)
+    }
+
+    /// Cosine weighted average radiance around `n`, from the spherical harmonics.
+    pub fn irradiance(&self, n: Vec3) -> Vec3 {
+        // Clamped cosine convolution per band, divided by pi.
+        let bands = [1., 2. / 3., 2. / 3., 2. / 3., 0.25, 0.25, 0.25, 0.25, 0.25];
+        let basis = sh_basis(n);
+        let mut e = Vec3::ZERO;
+        for i in 0..9 {
+            e += self.irradiance_sh[i] * basis[i] * bands[i];
+        }
+        e.max(Vec3::ZERO)
+    }
+
+    /// Radiance along `direction` blurred by a GGX lobe of the given roughness.
+    pub fn specular(&self, direction: Vec3, roughness: f32) -> Vec3 {
+        if self.specular_mips.is_empty() {
+            return self.sample(direction);
+        }
+        let level = roughness.clamp(0., 1.) * self.specular_mips.len() as f32;
+        let lower = level.floor() as usize;
+        let upper = (lower + 1).min(self.specular_mips.len());
+        let mip = |i: usize| match i {
+            0 => self.sample(direction),
+            _ => self.specular_mips[i - 1].sample(direction),
+        };
+        mip(lower).lerp(mip(upper), level - lower as f32)
+    }
+
+    fn texel_direction(&self, x: u32, y: u32) -> Vec3 {
+        Self::uv_to_direction(
+            (x as f32 + 0.5) / self.width as f32,
+            (y as f32 + 0.5) / self.height as f32,
+        )
+    }
+
+    fn project_sh(&self) -> [Vec3; 9] {
+        let mut sh = [Vec3::ZERO; 9];
+        let texel_angle = 2. * PI * PI / (self.width * self.height) as f32;
+        for y in 0..self.height {
+            let sin_theta = ((y as f32 + 0.5) / self.height as f32 * PI).sin();
+            for x in 0..self.width {
+                let basis = sh_basis(self.texel_direction(x, y));
+                let radiance = self.pixel(x, y) * texel_angle * sin_theta;
+                for i in 0..9 {
+                    sh[i] += radiance * basis[i];
+                }
+            }
+        }
+        sh
+    }
+
+    fn prefilter_level(&self, width: u32, height: u32, roughness: f32) -> EnvironmentMap {
+        let alpha = (roughness * roughness).max(0.002);
+        let mut level = EnvironmentMap {
+            width,
+            height,
+            ..Default::default()
+        };
+
+        level.pixels = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let r = level.texel_direction(i % width, i / width);
+                let mut sum = Vec3::ZERO;
+                let mut weight = 0.;
+                for s in 0..SPECULAR_SAMPLES {
+                    let (u1, u2) = hammersley(s, SPECULAR_SAMPLES);
+                    let h = brdf::sample_ggx_half(r, alpha, u1, u2);
+                    let l = math::reflect(-r, h);
+                    let n_dot_l = r.dot(l);
+                    if n_dot_l > 0. {
+                        sum += self.sample(l) * n_dot_l;
+                        weight += n_dot_l;
+                    }
+                }
+                sum / weight.max(1e-6)
+            })
+            .collect();
+        level
+    }
+
+    /// Levels filtered from the full map, level `i` matching the GGX lobe at
+    /// roughness `i / (SPECULAR_LEVELS - 1)`.
+    fn prefilter_specular(&self) -> Vec<EnvironmentMap> {
+        (1..SPECULAR_LEVELS)
+            .map(|i| {
+                let width = (self.width >> i).max(MIN_LEVEL_WIDTH);
+                let height = (width / 2).max(1);
+                let roughness = i as f32 / (SPECULAR_LEVELS - 1) as f32;
+                self.prefilter_level(width, height, roughness)
+            })
+            .collect()
+    }
+
+    fn build_distribution(&mut self) {
+        let (w, h) = (self.width as usize, self.height as usize);
+        self.conditional_cdf = vec![0.; w * h];
+        self.marginal_cdf = vec![0.; h];
+
+        let mut total = 0.;
+        for y in 0..h {
+            let sin_theta = ((y as f32 + 0.5) / h as f32 * PI).sin();
+            let row = &mut self.conditional_cdf[y * w..(y + 1) * w];
+            let mut sum = 0.;
+            for (x, c) in row.iter_mut().enumerate() {
+                sum += (math::luminance(self.pixels[y * w + x]) + 1e-4) * sin_theta;
+                *c = sum;
+            }
+            row.iter_mut().for_each(|c| *c /= sum);
+            total += sum;
+            self.marginal_cdf[y] = total;
+        }
+        self.marginal_cdf.iter_mut().for_each(|c| *c /= total);
+        self.total_weight = total;
+    }
+
+    /// Probability density, per solid angle, of `sample_direction` returning `direction`.
+    pub fn pdf(&self, direction: Vec3) -> f32 {
+        if self.total_weight <= 0. {
+            return 0.;
+        }
+        let (u, v) = Self::direction_to_uv(direction);
+        let x = ((u - u.floor()) * self.width as f32) as u32 % self.width;
+        let y = ((v * self.height as f32) as u32).min(self.height - 1);
+        let sin_theta = ((y as f32 + 0.5) / self.height as f32 * PI).sin();
+
+        let weight = (math::luminance(self.pixel(x, y)) + 1e-4) * sin_theta;
+        let pixel_pdf = weight / self.total_weight;
+        pixel_pdf * (self.width * self.height) as f32 / (2. * PI * PI * sin_theta.max(1e-6))
+    }
+
+    /// Picks a direction proportionally to the map luminance.
+    pub fn sample_direction(&self, u1: f32, u2: f32) -> (Vec3, f32) {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let y = self.marginal_cdf.partition_point(|c| *c < u1).min(h - 1);
+        let row = &self.conditional_cdf[y * w..(y + 1) * w];
+        let x = row.partition_point(|c| *c < u2).min(w - 1);
+
+        let direction = self.texel_direction(x as u32, y as u32);
+        (direction, self.pdf(direction))
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use image::ImageError;
 
 #[derive(Debug, Clone)]
@@ -7,6 +9,16 @@ pub enum AppError {
     ErrorString(String)
 }
 
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::ErrorIo(e) => write!(f, "I/O error: {}", e),
+            AppError::ErrorLoadTexture(e) => write!(f, "image error: {}", e),
+            AppError::ErrorString(e) => write!(f, "{}", e),
+        }
+    }
+}
+
 impl From<std::io::Error> for AppError {
     fn from(value: std::io::Error) -> Self {
         AppError::ErrorIo(format!("{}", value))
@@ -1,6 +1,8 @@
 
-use super::{errors::AppError, texture::Texture};
-use image::ImageReader;
+use glam::{vec3, Vec3};
+
+use super::{environment::EnvironmentMap, errors::AppError, texture::Texture};
+use image::{ColorType, ImageReader, Luma, Rgb, RgbaImage};
 
 pub struct ImageUtils {
 }
@@ -23,4 +25,57 @@ impl ImageUtils {
             bytes
         })
     }
-}
\ No newline at end of file
+
+    /// Loads an equirectangular environment, keeping HDR values unclamped, and
+    /// precomputes its image based lighting data.
+    pub fn load_environment(path: impl Into<String>) -> Result<EnvironmentMap, AppError> {
+        let p: String = path.into();
+        let img = ImageReader::open(p.clone())?.decode()?;
+        // 8 and 16 bit images are sRGB encoded, float images are already linear.
+        let gamma = match img.color() {
+            ColorType::Rgb32F | ColorType::Rgba32F => 1.,
+            _ => 2.2,
+        };
+        let rgb = img.into_rgb32f();
+        let (w, h) = (rgb.width(), rgb.height());
+
+        let pixels = rgb
+            .pixels()
+            .map(|c| vec3(c.0[0], c.0[1], c.0[2]).powf(gamma))
+            .collect();
+
+        Ok(EnvironmentMap::new(p, w, h, pixels))
+    }
+
+    pub fn save_rgba(path: &str, width: u32, height: u32, bytes: &[u8]) -> Result<(), AppError> {
+        let img = RgbaImage::from_raw(width, height, bytes.to_vec())
+            .ok_or_else(|| AppError::ErrorString(format!("Invalid image size for {}", path)))?;
+        img.save(path)?;
+        Ok(())
+    }
+
+    /// Saves values in [0, 1] as a 16 bit grayscale image.
+    pub fn save_gray16(
+        path: &str,
+        width: u32,
+        height: u32,
+        values: &[f32],
+    ) -> Result<(), AppError> {
+        let img = image::ImageBuffer::from_fn(width, height, |x, y| {
+            let v = values[(y * width + x) as usize].clamp(0., 1.);
+            Luma([(v * 65535.) as u16])
+        });
+        img.save(path)?;
+        Ok(())
+    }
+
+    /// Saves values in [0, 1] as an 8 bit RGB image.
+    pub fn save_rgb(path: &str, width: u32, height: u32, values: &[Vec3]) -> Result<(), AppError> {
+        let img = image::ImageBuffer::from_fn(width, height, |x, y| {
+            let v = values[(y * width + x) as usize].clamp(Vec3::ZERO, Vec3::ONE) * 255.;
+            Rgb([v.x as u8, v.y as u8, v.z as u8])
+        });
+        img.save(path)?;
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 use glam::Vec3;
 
+use super::math;
+use super::texture::Texture;
+
+static TRI_PLANAR_BLENDING: f32 = 4.;
 
 #[derive(Debug, Copy, Clone)]
 pub enum MaterialType {
@@ -13,6 +17,67 @@ pub enum MaterialType {
     },
 }
 
+#[derive(Debug, Copy, Clone)]
+pub enum NormalMap {
+    /// Gray scale height texture whose slope tilts the shading normal.
+    Bump {
+        texture: usize,
+        scale: f32,
+        strength: f32,
+    },
+    /// Tangent space normal texture (RGB encoded).
+    Tangent {
+        texture: usize,
+        scale: f32,
+        strength: f32,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Displacement {
+    pub texture: usize,
+    pub scale: f32,
+    pub amplitude: f32,
+    /// Upper bound of the height texture slope, per texture unit.
+    pub lipschitz: f32,
+}
+
+/// Metallic/roughness parameters evaluated with a Cook-Torrance GGX BRDF.
+#[derive(Debug, Copy, Clone)]
+pub struct Pbr {
+    pub base_color: Vec3,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Scales the dielectric reflectance derived from `ior`.
+    pub specular: f32,
+    pub ior: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+}
+
+impl Default for Pbr {
+    fn default() -> Self {
+        Self {
+            base_color: Vec3::splat(0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 1.0,
+            ior: 1.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.1,
+        }
+    }
+}
+
+/// Light scattering below the surface of wax, skin or marble like materials.
+#[derive(Debug, Copy, Clone)]
+pub struct Subsurface {
+    /// Albedo of each scattering event inside the material.
+    pub color: Vec3,
+    /// Mean distance travelled by light between two scattering events.
+    pub radius: f32,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Material {
     pub ambience: f32,
@@ -23,6 +88,11 @@ pub struct Material {
     pub texture: Option<usize>,
     pub kind: MaterialType,
     pub emission_power: f32,
+    pub normal_map: Option<NormalMap>,
+    pub displacement: Option<Displacement>,
+    /// When set, the material is shaded with the PBR model instead of Phong.
+    pub pbr: Option<Pbr>,
+    pub subsurface: Option<Subsurface>,
 }
 
 impl Default for Material {
@@ -36,11 +106,63 @@ impl Default for Material {
             texture: None,
             kind: MaterialType::Reflective { roughness: 1.0 },
             emission_power: 0.0,
+            normal_map: None,
+            displacement: None,
+            pbr: None,
+            subsurface: None,
         }
     }
 }
 
+impl Displacement {
+    pub fn height(&self, p: Vec3, n: Vec3, textures: &[Texture]) -> f32 {
+        self.amplitude
+            * math::tri_planar_height(
+                p,
+                n,
+                TRI_PLANAR_BLENDING,
+                self.scale,
+                &textures[self.texture],
+            )
+    }
+
+    /// Factor keeping the displaced distance a conservative bound.
+    pub fn step_scale(&self) -> f32 {
+        1. / (1. + self.amplitude * self.scale * self.lipschitz)
+    }
+}
+
 impl Material {
+    pub fn shading_normal(&self, p: Vec3, n: Vec3, textures: &[Texture]) -> Vec3 {
+        match self.normal_map {
+            Some(NormalMap::Bump {
+                texture,
+                scale,
+                strength,
+            }) => math::tri_planar_bump(
+                p,
+                n,
+                TRI_PLANAR_BLENDING,
+                scale,
+                strength,
+                &textures[texture],
+            ),
+            Some(NormalMap::Tangent {
+                texture,
+                scale,
+                strength,
+            }) => math::tri_planar_normal(
+                p,
+                n,
+                TRI_PLANAR_BLENDING,
+                scale,
+                strength,
+                &textures[texture],
+            ),
+            None => n,
+        }
+    }
+
     pub fn fresnel(
         &self,
         incident: Vec3,
@@ -70,6 +70,82 @@ pub fn tri_planar_mapping(p: Vec3, n: Vec3, blending: f32, scale: f32, tex: &Tex
     x * bw.x + y * bw.y + z * bw.z
 }
 
+pub fn tri_planar_weights(n: Vec3, blending: f32) -> Vec3 {
+    let bw = n.abs().powf(blending);
+    bw / (bw.x + bw.y + bw.z)
+}
+
+pub fn luminance(c: Vec3) -> f32 {
+    c.dot(vec3(0.2126, 0.7152, 0.0722))
+}
+
+pub fn tri_planar_height(p: Vec3, n: Vec3, blending: f32, scale: f32, tex: &Texture) -> f32 {
+    let xy = p.xy() * scale;
+    let xz = p.xz() * scale;
+    let yz = p.yz() * scale;
+
+    let x = luminance(tex.from_uv_bilinear(yz.x, yz.y));
+    let y = luminance(tex.from_uv_bilinear(xz.x, xz.y));
+    let z = luminance(tex.from_uv_bilinear(xy.x, xy.y));
+
+    let bw = tri_planar_weights(n, blending);
+    x * bw.x + y * bw.y + z * bw.z
+}
+
+fn height_slope(tex: &Texture, uv: Vec2) -> Vec2 {
+    let e = 1. / tex.width.max(tex.height) as f32;
+    let h = luminance(tex.from_uv_bilinear(uv.x, uv.y));
+    let hu = luminance(tex.from_uv_bilinear(uv.x + e, uv.y));
+    let hv = luminance(tex.from_uv_bilinear(uv.x, uv.y + e));
+    vec2(hu - h, hv - h) / e
+}
+
+/// Tilts the normal `n` along the slope of a tri-planar projected height texture.
+pub fn tri_planar_bump(
+    p: Vec3,
+    n: Vec3,
+    blending: f32,
+    scale: f32,
+    strength: f32,
+    tex: &Texture,
+) -> Vec3 {
+    let gx = height_slope(tex, p.yz() * scale);
+    let gy = height_slope(tex, p.xz() * scale);
+    let gz = height_slope(tex, p.xy() * scale);
+
+    let bw = tri_planar_weights(n, blending);
+    let grad =
+        (vec3(0., gx.x, gx.y) * bw.x + vec3(gy.x, 0., gy.y) * bw.y + vec3(gz.x, gz.y, 0.) * bw.z)
+            * scale;
+    let surface_grad = grad - n * grad.dot(n);
+
+    (n - strength * surface_grad).normalize()
+}
+
+/// Blends a tri-planar projected tangent space normal texture into `n` (UDN blending).
+pub fn tri_planar_normal(
+    p: Vec3,
+    n: Vec3,
+    blending: f32,
+    scale: f32,
+    strength: f32,
+    tex: &Texture,
+) -> Vec3 {
+    let xy = p.xy() * scale;
+    let xz = p.xz() * scale;
+    let yz = p.yz() * scale;
+
+    let tx = tex.from_uv_bilinear(yz.x, yz.y).xy() * 2. - 1.;
+    let ty = tex.from_uv_bilinear(xz.x, xz.y).xy() * 2. - 1.;
+    let tz = tex.from_uv_bilinear(xy.x, xy.y).xy() * 2. - 1.;
+
+    let bw = tri_planar_weights(n, blending);
+    let offset =
+        vec3(0., tx.x, tx.y) * bw.x + vec3(ty.x, 0., ty.y) * bw.y + vec3(tz.x, tz.y, 0.) * bw.z;
+
+    (n + strength * offset).normalize()
+}
+
 pub fn fog(col: Vec3, t: f32, ray: &Ray, b: f32) -> Vec3 {
     let fog_amount = (b) * (-ray.origin.y * b).exp() * (1.0 - (-t * ray.direction.y * b).exp())
         / ray.direction.y;
@@ -2,4 +2,7 @@ pub mod errors;
 pub mod image;
 pub mod math;
 pub mod texture;
-pub mod materials;
\ No newline at end of file
+pub mod materials;
+pub mod brdf;
+pub mod noise;
+pub mod environment;
@@ -0,0 +1,681 @@
+use glam::{vec2, vec3, Vec2, Vec3};
+
+// Normalisation factors keeping each basis roughly within [-1, 1].
+static PERLIN2_SCALE: f32 = std::f32::consts::SQRT_2;
+static PERLIN3_SCALE: f32 = 1.0;
+static SIMPLEX2_SCALE: f32 = 99.0;
+static SIMPLEX3_SCALE: f32 = 76.0;
+
+static SIMPLEX2_F: f32 = 0.366_025_42; // (sqrt(3) - 1) / 2
+static SIMPLEX2_G: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+static SIMPLEX3_F: f32 = 1. / 3.;
+static SIMPLEX3_G: f32 = 1. / 6.;
+
+static GRADIENTS2: [Vec2; 8] = [
+    vec2(1., 0.),
+    vec2(-1., 0.),
+    vec2(0., 1.),
+    vec2(0., -1.),
+    vec2(0.707_106_77, 0.707_106_77),
+    vec2(-0.707_106_77, 0.707_106_77),
+    vec2(0.707_106_77, -0.707_106_77),
+    vec2(-0.707_106_77, -0.707_106_77),
+];
+
+static GRADIENTS3: [Vec3; 12] = [
+    vec3(1., 1., 0.),
+    vec3(-1., 1., 0.),
+    vec3(1., -1., 0.),
+    vec3(-1., -1., 0.),
+    vec3(1., 0., 1.),
+    vec3(-1., 0., 1.),
+    vec3(1., 0., -1.),
+    vec3(-1., 0., -1.),
+    vec3(0., 1., 1.),
+    vec3(0., -1., 1.),
+    vec3(0., 1., -1.),
+    vec3(0., -1., -1.),
+];
+
+/// Noise basis used by [`Fbm`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Basis {
+    Perlin,
+    Simplex,
+}
+
+/// Distances to the two closest feature points and the gradient of the closest one.
+#[derive(Debug, Copy, Clone)]
+pub struct Worley<T> {
+    pub f1: f32,
+    /// Approximate: only the neighbouring cells are searched, so a closer
+    /// second point two cells away is occasionally missed.
+    pub f2: f32,
+    pub gradient: T,
+}
+
+/// Fractal sum of noise octaves.
+#[derive(Debug, Copy, Clone)]
+pub struct Fbm {
+    pub seed: u32,
+    pub basis: Basis,
+    pub octaves: u32,
+    pub frequency: f32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            basis: Basis::Perlin,
+            octaves: 5,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed.wrapping_mul(0x27d4_eb2d)
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297a_2d39);
+    h ^ (h >> 15)
+}
+
+fn hash_unit(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    (hash(x, y, z, seed) >> 8) as f32 / (1 << 24) as f32
+}
+
+fn gradient2(x: i32, y: i32, seed: u32) -> Vec2 {
+    GRADIENTS2[(hash(x, y, 0, seed) & 7) as usize]
+}
+
+fn gradient3(x: i32, y: i32, z: i32, seed: u32) -> Vec3 {
+    GRADIENTS3[(hash(x, y, z, seed) % 12) as usize]
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn fade_derivative(t: f32) -> f32 {
+    30. * t * t * (t * (t - 2.) + 1.)
+}
+
+/// 2D gradient noise and its derivative.
+pub fn perlin2(p: Vec2, seed: u32) -> (f32, Vec2) {
+    let i = p.floor();
+    let f = p - i;
+    let (ix, iy) = (i.x as i32, i.y as i32);
+
+    let ga = gradient2(ix, iy, seed);
+    let gb = gradient2(ix + 1, iy, seed);
+    let gc = gradient2(ix, iy + 1, seed);
+    let gd = gradient2(ix + 1, iy + 1, seed);
+
+    let va = ga.dot(f);
+    let vb = gb.dot(f - vec2(1., 0.));
+    let vc = gc.dot(f - vec2(0., 1.));
+    let vd = gd.dot(f - vec2(1., 1.));
+
+    let u = vec2(fade(f.x), fade(f.y));
+    let du = vec2(fade_derivative(f.x), fade_derivative(f.y));
+
+    let k = va - vb - vc + vd;
+    let value = va + u.x * (vb - va) + u.y * (vc - va) + u.x * u.y * k;
+    let derivative = ga
+        + u.x * (gb - ga)
+        + u.y * (gc - ga)
+        + u.x * u.y * (ga - gb - gc + gd)
+        + du * (vec2(u.y, u.x) * k + vec2(vb - va, vc - va));
+
+    (value * PERLIN2_SCALE, derivative * PERLIN2_SCALE)
+}
+
+/// 3D gradient noise and its derivative.
+pub fn perlin3(p: Vec3, seed: u32) -> (f32, Vec3) {
+    let i = p.floor();
+    let f = p - i;
+    let (ix, iy, iz) = (i.x as i32, i.y as i32, i.z as i32);
+
+    let ga = gradient3(ix, iy, iz, seed);
+    let gb = gradient3(ix + 1, iy, iz, seed);
+    let gc = gradient3(ix, iy + 1, iz, seed);
+    let gd = gradient3(ix + 1, iy + 1, iz, seed);
+    let ge = gradient3(ix, iy, iz + 1, seed);
+    let gf = gradient3(ix + 1, iy, iz + 1, seed);
+    let gg = gradient3(ix, iy + 1, iz + 1, seed);
+    let gh = gradient3(ix + 1, iy + 1, iz + 1, seed);
+
+    let va = ga.dot(f);
+    let vb = gb.dot(f - vec3(1., 0., 0.));
+    let vc = gc.dot(f - vec3(0., 1., 0.));
+    let vd = gd.dot(f - vec3(1., 1., 0.));
+    let ve = ge.dot(f - vec3(0., 0., 1.));
+    let vf = gf.dot(f - vec3(1., 0., 1.));
+    let vg = gg.dot(f - vec3(0., 1., 1.));
+    let vh = gh.dot(f - vec3(1., 1., 1.));
+
+    let u = vec3(fade(f.x), fade(f.y), fade(f.z));
+    let du = vec3(
+        fade_derivative(f.x),
+        fade_derivative(f.y),
+        fade_derivative(f.z),
+    );
+
+    let k1 = vb - va;
+    let k2 = vc - va;
+    let k3 = ve - va;
+    let k4 = va - vb - vc + vd;
+    let k5 = va - vc - ve + vg;
+    let k6 = va - vb - ve + vf;
+    let k7 = -va + vb + vc - vd + ve - vf - vg + vh;
+
+    let value = va
+        + u.x * k1
+        + u.y * k2
+        + u.z * k3
+        + u.x * u.y * k4
+        + u.y * u.z * k5
+        + u.z * u.x * k6
+        + u.x * u.y * u.z * k7;
+
+    let derivative = ga
+        + u.x * (gb - ga)
+        + u.y * (gc - ga)
+        + u.z * (ge - ga)
+        + u.x * u.y * (ga - gb - gc + gd)
+        + u.y * u.z * (ga - gc - ge + gg)
+        + u.z * u.x * (ga - gb - ge + gf)
+        + u.x * u.y * u.z * (-ga + gb + gc - gd + ge - gf - gg + gh)
+        + du * vec3(
+            k1 + k4 * u.y + k6 * u.z + k7 * u.y * u.z,
+            k2 + k5 * u.z + k4 * u.x + k7 * u.z * u.x,
+            k3 + k6 * u.x + k5 * u.y + k7 * u.x * u.y,
+        );
+
+    (value * PERLIN3_SCALE, derivative * PERLIN3_SCALE)
+}
+
+/// 2D simplex noise and its derivative.
+pub fn simplex2(p: Vec2, seed: u32) -> (f32, Vec2) {
+    let s = (p.x + p.y) * SIMPLEX2_F;
+    let i = (p + s).floor();
+    let t = (i.x + i.y) * SIMPLEX2_G;
+    let x0 = p - (i - t);
+
+    let i1 = if x0.x > x0.y {
+        vec2(1., 0.)
+    } else {
+        vec2(0., 1.)
+    };
+    let corners = [
+        (Vec2::ZERO, x0),
+        (i1, x0 - i1 + SIMPLEX2_G),
+        (Vec2::ONE, x0 - 1. + 2. * SIMPLEX2_G),
+    ];
+
+    let mut value = 0.;
+    let mut derivative = Vec2::ZERO;
+    for (offset, x) in corners {
+        let t = 0.5 - x.length_squared();
+        if t <= 0. {
+            continue;
+        }
+        let c = i + offset;
+        let g = gradient2(c.x as i32, c.y as i32, seed);
+        let gx = g.dot(x);
+        let t2 = t * t;
+        value += t2 * t2 * gx;
+        derivative += t2 * t2 * g - 8. * t2 * t * gx * x;
+    }
+
+    (value * SIMPLEX2_SCALE, derivative * SIMPLEX2_SCALE)
+}
+
+/// 3D simplex noise and its derivative.
+pub fn simplex3(p: Vec3, seed: u32) -> (f32, Vec3) {
+    let s = (p.x + p.y + p.z) * SIMPLEX3_F;
+    let i = (p + s).floor();
+    let t = (i.x + i.y + i.z) * SIMPLEX3_G;
+    let x0 = p - (i - t);
+
+    let (i1, i2) = if x0.x >= x0.y {
+        if x0.y >= x0.z {
+            (vec3(1., 0., 0.), vec3(1., 1., 0.))
+        } else if x0.x >= x0.z {
+            (vec3(1., 0., 0.), vec3(1., 0., 1.))
+        } else {
+            (vec3(0., 0., 1.), vec3(1., 0., 1.))
+        }
+    } else if x0.y < x0.z {
+        (vec3(0., 0., 1.), vec3(0., 1., 1.))
+    } else if x0.x < x0.z {
+        (vec3(0., 1., 0.), vec3(0., 1., 1.))
+    } else {
+        (vec3(0., 1., 0.), vec3(1., 1., 0.))
+    };
+
+    let corners = [
+        (Vec3::ZERO, x0),
+        (i1, x0 - i1 + SIMPLEX3_G),
+        (i2, x0 - i2 + 2. * SIMPLEX3_G),
+        (Vec3::ONE, x0 - 1. + 3. * SIMPLEX3_G),
+    ];
+
+    let mut value = 0.;
+    let mut derivative = Vec3::ZERO;
+    for (offset, x) in corners {
+        let t = 0.5 - x.length_squared();
+        if t <= 0. {
+            continue;
+        }
+        let c = i + offset;
+        let g = gradient3(c.x as i32, c.y as i32, c.z as i32, seed);
+        let gx = g.dot(x);
+        let t2 = t * t;
+        value += t2 * t2 * gx;
+        derivative += t2 * t2 * g - 8. * t2 * t * gx * x;
+    }
+
+    (value * SIMPLEX3_SCALE, derivative * SIMPLEX3_SCALE)
+}
+
+/// 2D cellular noise with one jittered feature point per unit cell, searched
+/// in the 3x3 cells around `p`.
+pub fn worley2(p: Vec2, seed: u32) -> Worley<Vec2> {
+    let i = p.floor();
+    let mut f1 = f32::MAX;
+    let mut f2 = f32::MAX;
+    let mut nearest = Vec2::ZERO;
+
+    for y in -1..=1 {
+        for x in -1..=1 {
+            let (cx, cy) = (i.x as i32 + x, i.y as i32 + y);
+            let feature = vec2(cx as f32, cy as f32)
+                + vec2(hash_unit(cx, cy, 0, seed), hash_unit(cx, cy, 1, seed));
+            let d = p.distance(feature);
+            if d < f1 {
+                f2 = f1;
+                f1 = d;
+                nearest = feature;
+            } else if d < f2 {
+                f2 = d;
+            }
+        }
+    }
+
+    Worley {
+        f1,
+        f2,
+        gradient: (p - nearest).normalize_or_zero(),
+    }
+}
+
+/// 3D cellular noise with one jittered feature point per unit cell, searched
+/// in the 3x3x3 cells around `p`.
+pub fn worley3(p: Vec3, seed: u32) -> Worley<Vec3> {
+    let i = p.floor();
+    let mut f1 = f32::MAX;
+    let mut f2 = f32::MAX;
+    let mut nearest = Vec3::ZERO;
+
+    for z in -1..=1 {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let (cx, cy, cz) = (i.x as i32 + x, i.y as i32 + y, i.z as i32 + z);
+                let feature = vec3(cx as f32, cy as f32, cz as f32)
+                    + vec3(
+                        hash_unit(cx, cy, cz, seed),
+                        hash_unit(cx, cy, cz, seed ^ 0x68e3_1da4),
+                        hash_unit(cx, cy, cz, seed ^ 0xb529_7a4d),
+                    );
+                let d = p.distance(feature);
+                if d < f1 {
+                    f2 = f1;
+                    f1 = d;
+                    nearest = feature;
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+    }
+
+    Worley {
+        f1,
+        f2,
+        gradient: (p - nearest).normalize_or_zero(),
+    }
+}
+
+impl Fbm {
+    fn basis2(&self, p: Vec2, seed: u32) -> (f32, Vec2) {
+        match self.basis {
+            Basis::Perlin => perlin2(p, seed),
+            Basis::Simplex => simplex2(p, seed),
+        }
+    }
+
+    fn basis3(&self, p: Vec3, seed: u32) -> (f32, Vec3) {
+        match self.basis {
+            Basis::Perlin => perlin3(p, seed),
+            Basis::Simplex => simplex3(p, seed),
+        }
+    }
+
+    /// Sum of all octaves and its derivative.
+    pub fn sample2(&self, p: Vec2) -> (f32, Vec2) {
+        let mut value = 0.;
+        let mut derivative = Vec2::ZERO;
+        let mut amplitude = 1.;
+        let mut frequency = self.frequency;
+
+        for octave in 0..self.octaves {
+            let (n, d) = self.basis2(p * frequency, self.seed.wrapping_add(octave));
+            value += amplitude * n;
+            derivative += amplitude * frequency * d;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        (value, derivative)
+    }
+
+    /// Sum of all octaves and its derivative.
+    pub fn sample3(&self, p: Vec3) -> (f32, Vec3) {
+        let mut value = 0.;
+        let mut derivative = Vec3::ZERO;
+        let mut amplitude = 1.;
+        let mut frequency = self.frequency;
+
+        for octave in 0..self.octaves {
+            let (n, d) = self.basis3(p * frequency, self.seed.wrapping_add(octave));
+            value += amplitude * n;
+            derivative += amplitude * frequency * d;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        (value, derivative)
+    }
+
+    /// Ridged fBm: octaves of `(1 - |n|)^2`, giving sharp crests.
+    pub fn ridged2(&self, p: Vec2) -> (f32, Vec2) {
+        let mut value = 0.;
+        let mut derivative = Vec2::ZERO;
+        let mut amplitude = 1.;
+        let mut frequency = self.frequency;
+
+        for octave in 0..self.octaves {
+            let (n, d) = self.basis2(p * frequency, self.seed.wrapping_add(octave));
+            let ridge = 1. - n.abs();
+            value += amplitude * ridge * ridge;
+            derivative += amplitude * frequency * -2. * ridge * n.signum() * d;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        (value, derivative)
+    }
+
+    /// Ridged fBm: octaves of `(1 - |n|)^2`, giving sharp crests.
+    pub fn ridged3(&self, p: Vec3) -> (f32, Vec3) {
+        let mut value = 0.;
+        let mut derivative = Vec3::ZERO;
+        let mut amplitude = 1.;
+        let mut frequency = self.frequency;
+
+        for octave in 0..self.octaves {
+            let (n, d) = self.basis3(p * frequency, self.seed.wrapping_add(octave));
+            let ridge = 1. - n.abs();
+            value += amplitude * ridge * ridge;
+            derivative += amplitude * frequency * -2. * ridge * n.signum() * d;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        (value, derivative)
+    }
+
+    /// fBm sampled at `p + strength * q(p)`, where `q` is itself an fBm vector field.
+    pub fn warped2(&self, p: Vec2, strength: f32) -> (f32, Vec2) {
+        let warp = Fbm {
+            seed: self.seed.wrapping_add(1013),
+            ..*self
+        };
+        let (qx, dqx) = warp.sample2(p);
+        let (qy, dqy) = warp.sample2(p + vec2(5.2, 1.3));
+
+        let (value, d) = self.sample2(p + strength * vec2(qx, qy));
+        (value, d + strength * (d.x * dqx + d.y * dqy))
+    }
+
+    /// fBm sampled at `p + strength * q(p)`, where `q` is itself an fBm vector field.
+    pub fn warped3(&self, p: Vec3, strength: f32) -> (f32, Vec3) {
+        let warp = Fbm {
+            seed: self.seed.wrapping_add(1013),
+            ..*self
+        };
+        let (qx, dqx) = warp.sample3(p);
+        let (qy, dqy) = warp.sample3(p + vec3(5.2, 1.3, 2.8));
+        let (qz, dqz) = warp.sample3(p + vec3(1.7, 9.2, 4.1));
+
+        let (value, d) = self.sample3(p + strength * vec3(qx, qy, qz));
+        (value, d + strength * (d.x * dqx + d.y * dqy + d.z * dqz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static EPS: f32 = 1e-3;
+
+    fn points2() -> impl Iterator<Item = Vec2> {
+        (0..400).map(|i| vec2(i as f32 * 0.173 - 31.7, (i * 7 % 53) as f32 * 0.291 - 5.3))
+    }
+
+    fn points3() -> impl Iterator<Item = Vec3> {
+        (0..400).map(|i| {
+            vec3(
+                i as f32 * 0.173 - 31.7,
+                (i * 7 % 53) as f32 * 0.291 - 5.3,
+                (i * 13 % 37) as f32 * 0.357 + 2.1,
+            )
+        })
+    }
+
+    fn numeric2(f: impl Fn(Vec2) -> f32, p: Vec2) -> Vec2 {
+        vec2(
+            (f(p + vec2(EPS, 0.)) - f(p - vec2(EPS, 0.))) / (2. * EPS),
+            (f(p + vec2(0., EPS)) - f(p - vec2(0., EPS))) / (2. * EPS),
+        )
+    }
+
+    fn numeric3(f: impl Fn(Vec3) -> f32, p: Vec3) -> Vec3 {
+        vec3(
+            (f(p + vec3(EPS, 0., 0.)) - f(p - vec3(EPS, 0., 0.))) / (2. * EPS),
+            (f(p + vec3(0., EPS, 0.)) - f(p - vec3(0., EPS, 0.))) / (2. * EPS),
+            (f(p + vec3(0., 0., EPS)) - f(p - vec3(0., 0., EPS))) / (2. * EPS),
+        )
+    }
+
+    fn assert_derivative2(f: impl Fn(Vec2) -> (f32, Vec2), tolerance: f32) {
+        for p in points2() {
+            let analytic = f(p).1;
+            let numeric = numeric2(|q| f(q).0, p);
+            assert!(
+                (analytic - numeric).length() < tolerance,
+                "{p}: analytic {analytic} numeric {numeric}"
+            );
+        }
+    }
+
+    fn assert_derivative3(f: impl Fn(Vec3) -> (f32, Vec3), tolerance: f32) {
+        for p in points3() {
+            let analytic = f(p).1;
+            let numeric = numeric3(|q| f(q).0, p);
+            assert!(
+                (analytic - numeric).length() < tolerance,
+                "{p}: analytic {analytic} numeric {numeric}"
+            );
+        }
+    }
+
+    /// Whether the one sided differences at `p` disagree, that is `f` has a
+    /// crease within `EPS` where finite differences are meaningless.
+    fn creased2(f: impl Fn(Vec2) -> f32, p: Vec2, tolerance: f32) -> bool {
+        [vec2(EPS, 0.), vec2(0., EPS)].into_iter().any(|e| {
+            let forward = (f(p + e) - f(p)) / EPS;
+            let backward = (f(p) - f(p - e)) / EPS;
+            (forward - backward).abs() > tolerance
+        })
+    }
+
+    fn creased3(f: impl Fn(Vec3) -> f32, p: Vec3, tolerance: f32) -> bool {
+        [vec3(EPS, 0., 0.), vec3(0., EPS, 0.), vec3(0., 0., EPS)]
+            .into_iter()
+            .any(|e| {
+                let forward = (f(p + e) - f(p)) / EPS;
+                let backward = (f(p) - f(p - e)) / EPS;
+                (forward - backward).abs() > tolerance
+            })
+    }
+
+    /// Same as `assert_derivative2` for functions with creases, such as ridges
+    /// or cell borders, skipping the few points next to one.
+    fn assert_creased_derivative2(f: impl Fn(Vec2) -> (f32, Vec2), tolerance: f32) {
+        let smooth: Vec<Vec2> = points2()
+            .filter(|p| !creased2(|q| f(q).0, *p, tolerance))
+            .collect();
+        assert!(smooth.len() > points2().count() * 9 / 10);
+        for p in smooth {
+            let analytic = f(p).1;
+            let numeric = numeric2(|q| f(q).0, p);
+            assert!(
+                (analytic - numeric).length() < tolerance,
+                "{p}: analytic {analytic} numeric {numeric}"
+            );
+        }
+    }
+
+    fn assert_creased_derivative3(f: impl Fn(Vec3) -> (f32, Vec3), tolerance: f32) {
+        let smooth: Vec<Vec3> = points3()
+            .filter(|p| !creased3(|q| f(q).0, *p, tolerance))
+            .collect();
+        assert!(smooth.len() > points3().count() * 9 / 10);
+        for p in smooth {
+            let analytic = f(p).1;
+            let numeric = numeric3(|q| f(q).0, p);
+            assert!(
+                (analytic - numeric).length() < tolerance,
+                "{p}: analytic {analytic} numeric {numeric}"
+            );
+        }
+    }
+
+    #[test]
+    fn noise_is_deterministic_per_seed() {
+        let p = vec3(1.37, -4.2, 8.9);
+        assert_eq!(perlin3(p, 7).0, perlin3(p, 7).0);
+        assert_eq!(simplex3(p, 7).0, simplex3(p, 7).0);
+        assert_eq!(worley3(p, 7).f1, worley3(p, 7).f1);
+        assert_ne!(perlin3(p, 7).0, perlin3(p, 8).0);
+        assert_ne!(simplex2(p.truncate(), 7).0, simplex2(p.truncate(), 8).0);
+        assert_ne!(worley2(p.truncate(), 7).f1, worley2(p.truncate(), 8).f1);
+    }
+
+    #[test]
+    fn perlin_vanishes_on_lattice() {
+        for i in -5..5 {
+            let p = vec2(i as f32, (i * 3) as f32);
+            assert!(perlin2(p, 3).0.abs() < 1e-6);
+            assert!(perlin3(p.extend(i as f32), 3).0.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        for p in points3() {
+            assert!(perlin2(p.truncate(), 1).0.abs() <= 1.05);
+            assert!(perlin3(p, 1).0.abs() <= 1.05);
+            assert!(simplex2(p.truncate(), 1).0.abs() <= 1.05);
+            assert!(simplex3(p, 1).0.abs() <= 1.05);
+        }
+    }
+
+    #[test]
+    fn gradient_noise_derivatives_match_finite_differences() {
+        assert_derivative2(|p| perlin2(p, 11), 0.02);
+        assert_derivative3(|p| perlin3(p, 11), 0.02);
+        assert_derivative2(|p| simplex2(p, 11), 0.05);
+        assert_derivative3(|p| simplex3(p, 11), 0.05);
+    }
+
+    #[test]
+    fn worley_distances_are_ordered() {
+        for p in points3() {
+            let w = worley3(p, 5);
+            assert!(w.f1 >= 0. && w.f1 <= w.f2);
+            assert!((w.gradient.length() - 1.).abs() < 1e-4);
+
+            let w = worley2(p.truncate(), 5);
+            assert!(w.f1 >= 0. && w.f1 <= w.f2);
+        }
+    }
+
+    #[test]
+    fn fbm_derivatives_match_finite_differences() {
+        let fbm = Fbm {
+            seed: 42,
+            octaves: 4,
+            frequency: 0.5,
+            ..Default::default()
+        };
+        assert_derivative2(|p| fbm.sample2(p), 0.05);
+        assert_derivative3(|p| fbm.sample3(p), 0.05);
+        assert_derivative2(|p| fbm.warped2(p, 0.5), 0.1);
+        assert_derivative3(|p| fbm.warped3(p, 0.5), 0.1);
+
+        let simplex = Fbm {
+            basis: Basis::Simplex,
+            ..fbm
+        };
+        assert_derivative3(|p| simplex.sample3(p), 0.1);
+    }
+
+    #[test]
+    fn ridged_derivatives_match_finite_differences() {
+        let fbm = Fbm {
+            seed: 42,
+            octaves: 4,
+            frequency: 0.5,
+            ..Default::default()
+        };
+        assert_creased_derivative2(|p| fbm.ridged2(p), 0.1);
+        assert_creased_derivative3(|p| fbm.ridged3(p), 0.1);
+    }
+
+    #[test]
+    fn worley_gradients_match_finite_differences() {
+        assert_creased_derivative2(
+            |p| {
+                let w = worley2(p, 5);
+                (w.f1, w.gradient)
+            },
+            0.01,
+        );
+        assert_creased_derivative3(
+            |p| {
+                let w = worley3(p, 5);
+                (w.f1, w.gradient)
+            },
+            0.01,
+        );
+    }
+}
@@ -36,6 +36,22 @@ impl Texture {
         self.pixel(x, y)
     }
 
+    pub fn from_uv_bilinear(&self, u: f32, v: f32) -> Vec3 {
+        let x = self.textel(u) * (self.width - 1) as f32;
+        let y = self.textel(v) * (self.height - 1) as f32;
+
+        let x0 = x.floor() as u32;
+        let y0 = y.floor() as u32;
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fx = x - x0 as f32;
+        let fy = y - y0 as f32;
+
+        let top = self.pixel(x0, y0).lerp(self.pixel(x1, y0), fx);
+        let bottom = self.pixel(x0, y1).lerp(self.pixel(x1, y1), fx);
+        top.lerp(bottom, fy)
+    }
+
     pub fn pixel(&self, x: u32, y: u32) -> Vec3 {
         let pos = (y * 3 * self.width + x * 3) as usize;
 
@@ -0,0 +1,251 @@
+use glam::Vec3;
+
+use crate::light::LightSource;
+use crate::media::henyey_greenstein;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::utils::math;
+use crate::utils::noise::Fbm;
+
+static MIN_TRANSMITTANCE: f32 = 0.01;
+/// Shortest adaptive step, so a zero or negative `min_step` still advances.
+static MIN_STEP: f32 = 1e-4;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Bounds {
+    /// Horizontal slab between two heights.
+    Layer {
+        bottom: f32,
+        top: f32,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    Box {
+        min: Vec3,
+        max: Vec3,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum DensityField {
+    /// fBm clouds filling a `Layer`, thicker in the middle of the layer.
+    Clouds {
+        fbm: Fbm,
+        coverage: f32,
+    },
+    /// fBm puff fading out towards the edge of a sphere.
+    Smoke {
+        fbm: Fbm,
+        center: Vec3,
+        radius: f32,
+    },
+    Custom(fn(Vec3) -> f32),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Stepping {
+    Fixed(usize),
+    /// Long steps through empty space, short ones inside the volume.
+    Adaptive {
+        min_step: f32,
+        max_step: f32,
+    },
+}
+
+/// Density field rendered by marching through it, such as clouds or smoke.
+#[derive(Debug, Copy, Clone)]
+pub struct Volume {
+    pub bounds: Bounds,
+    pub density: DensityField,
+    /// Extinction coefficient per unit density.
+    pub extinction: f32,
+    /// Scattering albedo.
+    pub albedo: Vec3,
+    /// Henyey-Greenstein asymmetry of the phase function.
+    pub anisotropy: f32,
+    pub stepping: Stepping,
+    pub max_distance: f32,
+    /// Samples taken towards each light for self shadowing.
+    pub light_steps: usize,
+    pub light_step_size: f32,
+    /// Strength of the powder term darkening the thin edges facing the light.
+    pub powder: f32,
+}
+
+impl Volume {
+    pub fn cloud_layer(bottom: f32, top: f32, fbm: Fbm, coverage: f32) -> Volume {
+        Volume {
+            bounds: Bounds::Layer { bottom, top },
+            density: DensityField::Clouds { fbm, coverage },
+            extinction: 2.,
+            albedo: Vec3::splat(0.95),
+            anisotropy: 0.4,
+            stepping: Stepping::Adaptive {
+                min_step: (top - bottom) / 16.,
+                max_step: (top - bottom) / 4.,
+            },
+            max_distance: 200.,
+            light_steps: 5,
+            light_step_size: (top - bottom) / 8.,
+            powder: 0.5,
+        }
+    }
+}
+
+impl Bounds {
+    /// Distances along `ray` where it enters and leaves the bounds.
+    pub fn intersect(&self, ray: &Ray) -> Option<(f32, f32)> {
+        let (t0, t1) = match *self {
+            Bounds::Layer { bottom, top } => {
+                if ray.direction.y.abs() < 1e-6 {
+                    if ray.origin.y < bottom || ray.origin.y > top {
+                        return None;
+                    }
+                    (0., f32::MAX)
+                } else {
+                    let ta = (bottom - ray.origin.y) / ray.direction.y;
+                    let tb = (top - ray.origin.y) / ray.direction.y;
+                    (ta.min(tb), ta.max(tb))
+                }
+            }
+            Bounds::Sphere { center, radius } => {
+                let oc = ray.origin - center;
+                let b = oc.dot(ray.direction);
+                let c = oc.length_squared() - radius * radius;
+                let disc = b * b - c;
+                if disc < 0. {
+                    return None;
+                }
+                let s = disc.sqrt();
+                (-b - s, -b + s)
+            }
+            Bounds::Box { min, max } => {
+                let inv = ray.direction.recip();
+                let ta = (min - ray.origin) * inv;
+                let tb = (max - ray.origin) * inv;
+                (ta.min(tb).max_element(), ta.max(tb).min_element())
+            }
+        };
+        if t1 < t0.max(0.) {
+            return None;
+        }
+        Some((t0.max(0.), t1))
+    }
+}
+
+impl DensityField {
+    pub fn at(&self, bounds: &Bounds, p: Vec3) -> f32 {
+        match *self {
+            DensityField::Clouds { fbm, coverage } => {
+                let height = match *bounds {
+                    Bounds::Layer { bottom, top } => {
+                        let h = ((p.y - bottom) / (top - bottom)).clamp(0., 1.);
+                        4. * h * (1. - h)
+                    }
+                    _ => 1.,
+                };
+                let n = 0.5 + 0.5 * fbm.sample3(p).0;
+                ((n - (1. - coverage)) * height).max(0.) / coverage.max(1e-3)
+            }
+            DensityField::Smoke {
+                fbm,
+                center,
+                radius,
+            } => {
+                let falloff = 1. - (p - center).length() / radius;
+                let n = 0.5 + 0.5 * fbm.sample3(p).0;
+                (n * falloff * 2. - 0.5).max(0.)
+            }
+            DensityField::Custom(f) => f(p),
+        }
+    }
+}
+
+impl Volume {
+    fn density(&self, p: Vec3) -> f32 {
+        self.density.at(&self.bounds, p)
+    }
+
+    /// Light reaching `p` through the volume, with Beer's law and the powder term.
+    fn light_energy(&self, p: Vec3, light_dir: Vec3) -> f32 {
+        let mut optical_depth = 0.;
+        for i in 0..self.light_steps {
+            let q = p + light_dir * (i as f32 + 0.5) * self.light_step_size;
+            optical_depth += self.density(q) * self.extinction * self.light_step_size;
+        }
+        let beer = (-optical_depth).exp();
+        let powder = 1. - (-2. * optical_depth).exp();
+        beer * math::mix(1., 2. * powder, self.powder)
+    }
+
+    /// Marches the volume along `ray` up to the surface at `t_max`. Returns the
+    /// transmittance and the light scattered towards the ray origin.
+    pub fn integrate(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        t_max: f32,
+        ambient: Vec3,
+        jitter: f32,
+    ) -> (Vec3, Vec3) {
+        let mut transmittance = Vec3::ONE;
+        let mut scattered = Vec3::ZERO;
+
+        let Some((t0, t1)) = self.bounds.intersect(ray) else {
+            return (transmittance, scattered);
+        };
+        let t1 = t1.min(t_max).min(self.max_distance);
+        if t1 <= t0 {
+            return (transmittance, scattered);
+        }
+
+        let fixed_step = match self.stepping {
+            Stepping::Fixed(steps) => Some((t1 - t0) / steps as f32),
+            Stepping::Adaptive { .. } => None,
+        };
+        let mut dt = fixed_step.unwrap_or(0.);
+        let mut t = t0;
+        let mut jitter = jitter;
+
+        while t < t1 && transmittance.max_element() > MIN_TRANSMITTANCE {
+            let p = ray.origin + ray.direction * (t + jitter * dt);
+            let density = self.density(p);
+
+            if let Stepping::Adaptive { min_step, max_step } = self.stepping {
+                let min_step = min_step.max(MIN_STEP);
+                let max_step = max_step.max(min_step);
+                // Step back and refine once the volume is entered.
+                if density > 0. && dt > min_step {
+                    dt = min_step;
+                    jitter = 0.;
+                    continue;
+                }
+                dt = if density > 0. { min_step } else { max_step };
+            }
+            let step = dt.min(t1 - t);
+
+            if density > 0. {
+                let sigma_t = self.extinction * density;
+
+                let mut light = ambient;
+                for l in &scene.lights {
+                    let light_dir = -l.direction(p);
+                    let phase = henyey_greenstein(ray.direction.dot(light_dir), self.anisotropy)
+                        * 4.
+                        * std::f32::consts::PI;
+                    light += l.albedo() * l.intensity() * phase * self.light_energy(p, light_dir);
+                }
+
+                let step_transmittance = (-sigma_t * step).exp();
+                let s = self.albedo * light;
+                scattered += transmittance * s * (1. - step_transmittance);
+                transmittance *= step_transmittance;
+            }
+            t += step;
+        }
+
+        (transmittance, scattered)
+    }
+}
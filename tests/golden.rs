@@ -0,0 +1,270 @@
+//! Renders the scenes in `tests/scenes` at low resolution and compares them
+//! with the reference images in `tests/golden`.
+//!
+//! Run with `BLESS=1 cargo test --test golden` to overwrite the references
+//! after an intended change. On failure the rendered image and a diff are
+//! written next to the test binaries, and their paths are printed.
+
+use std::path::{Path, PathBuf};
+
+use glam::{vec2, vec3, Vec2, Vec3};
+use ray_tracing::camera::Camera;
+use ray_tracing::ray_marching::RayMarching;
+use ray_tracing::renderer::Renderer;
+use ray_tracing::scene_file::SceneFile;
+use ray_tracing::utils::image::ImageUtils;
+
+static WIDTH: u32 = 96;
+static HEIGHT: u32 = 72;
+/// Root mean square difference allowed over the RGB channels, in [0, 1].
+static RMSE_TOLERANCE: f32 = 0.01;
+/// Channel difference above which a pixel is highlighted in the diff image.
+static DIFF_THRESHOLD: f32 = 0.05;
+
+struct Case {
+    name: &'static str,
+    scene: &'static str,
+    /// Overrides the scene's camera, as position and forward direction.
+    camera: Option<(Vec3, Vec3)>,
+    resolution: (u32, u32),
+}
+
+impl Case {
+    fn new(name: &'static str, scene: &'static str) -> Case {
+        Case {
+            name,
+            scene,
+            camera: None,
+            resolution: (WIDTH, HEIGHT),
+        }
+    }
+
+    fn camera(mut self, position: Vec3, forward: Vec3) -> Case {
+        self.camera = Some((position, forward));
+        self
+    }
+
+    fn resolution(mut self, width: u32, height: u32) -> Case {
+        self.resolution = (width, height);
+        self
+    }
+}
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn render(case: &Case) -> Vec<u8> {
+    let path = root().join("tests/scenes").join(case.scene);
+    let file = SceneFile::load(path.to_str().unwrap()).unwrap_or_else(|e| panic!("{}", e));
+    let mut camera = match (case.camera, file.camera) {
+        (Some((position, forward)), _) => Camera::new_with_pos(position, forward),
+        (None, Some(camera)) => camera,
+        (None, None) => Camera::new_with_pos(vec3(0., 1., 6.), vec3(0., 0., -1.)),
+    };
+    camera.resolution = vec2(case.resolution.0 as f32, case.resolution.1 as f32);
+
+    let mut renderer = Renderer::new();
+    renderer.render_image(&file.scene, &camera, 1)
+}
+
+fn channel(img: &[u8], i: usize) -> f32 {
+    img[i] as f32 / 255.
+}
+
+/// Root mean square difference over the RGB channels, and an image showing
+/// the differing pixels in red over a dimmed copy of the reference.
+fn compare(actual: &[u8], expected: &[u8]) -> (f32, Vec<Vec3>) {
+    let mut sum = 0.;
+    let diff = actual
+        .chunks(4)
+        .zip(expected.chunks(4))
+        .map(|(a, e)| {
+            let mut max: f32 = 0.;
+            for c in 0..3 {
+                let d = channel(a, c) - channel(e, c);
+                sum += d * d;
+                max = max.max(d.abs());
+            }
+            if max > DIFF_THRESHOLD {
+                vec3(1., 0., 0.)
+            } else {
+                vec3(channel(e, 0), channel(e, 1), channel(e, 2)) * 0.3
+            }
+        })
+        .collect();
+    let count = (actual.len() / 4 * 3).max(1) as f32;
+    ((sum / count).sqrt(), diff)
+}
+
+fn check(case: Case) {
+    let (w, h) = case.resolution;
+    let actual = render(&case);
+    let golden = root()
+        .join("tests/golden")
+        .join(format!("{}.png", case.name));
+
+    if std::env::var_os("BLESS").is_some() {
+        ImageUtils::save_rgba(golden.to_str().unwrap(), w, h, &actual).unwrap();
+        return;
+    }
+
+    let expected = match image::open(&golden) {
+        Ok(img) => img.to_rgba8(),
+        Err(e) => panic!(
+            "cannot read {}: {}; run with BLESS=1 to create it",
+            golden.display(),
+            e
+        ),
+    };
+    assert_eq!(
+        expected.dimensions(),
+        (w, h),
+        "{} has the wrong size",
+        golden.display()
+    );
+
+    let (rmse, diff) = compare(&actual, expected.as_raw());
+    if rmse > RMSE_TOLERANCE {
+        let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&out).unwrap();
+        let actual_path = out.join(format!("{}_actual.png", case.name));
+        let diff_path = out.join(format!("{}_diff.png", case.name));
+        ImageUtils::save_rgba(actual_path.to_str().unwrap(), w, h, &actual).unwrap();
+        ImageUtils::save_rgb(diff_path.to_str().unwrap(), w, h, &diff).unwrap();
+        panic!(
+            "{}: RMSE {:.4} above {}; rendered {}, diff {}",
+            case.name,
+            rmse,
+            RMSE_TOLERANCE,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn primitives() {
+    check(Case::new("primitives", "primitives.scene"));
+}
+
+#[test]
+fn operators() {
+    check(Case::new("operators", "operators.scene"));
+}
+
+#[test]
+fn lighting_directional() {
+    check(Case::new(
+        "lighting_directional",
+        "lighting_directional.scene",
+    ));
+}
+
+#[test]
+fn lighting_point() {
+    check(Case::new("lighting_point", "lighting_point.scene"));
+}
+
+#[test]
+fn lighting_sphere() {
+    check(Case::new("lighting_sphere", "lighting_sphere.scene"));
+}
+
+#[test]
+fn materials() {
+    check(Case::new("materials", "materials.scene"));
+}
+
+#[test]
+fn camera_top_down() {
+    check(
+        Case::new("camera_top_down", "primitives.scene")
+            .camera(vec3(0., 8., 0.5), vec3(0., -1., -0.05)),
+    );
+}
+
+#[test]
+fn camera_wide() {
+    check(
+        Case::new("camera_wide", "primitives.scene")
+            .camera(vec3(-5., 1.5, 4.), vec3(1., -0.2, -0.8))
+            .resolution(128, 48),
+    );
+}
+
+#[test]
+fn camera_tall() {
+    check(Case::new("camera_tall", "operators.scene").resolution(48, 96));
+}
+
+/// Points seen through a pixel project back onto it.
+#[test]
+fn camera_projection() {
+    let file = SceneFile::load(
+        root()
+            .join("tests/scenes/primitives.scene")
+            .to_str()
+            .unwrap(),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+    let mut camera = Camera::new_with_pos(vec3(1., 2., 5.), vec3(-0.3, -0.4, -1.));
+    camera.resolution = vec2(160., 90.);
+
+    for coord in [
+        vec2(0.5, 0.5),
+        vec2(80., 45.),
+        vec2(12.5, 70.25),
+        vec2(159.5, 89.5),
+    ] {
+        let ray = file.scene.camera_ray(&camera, coord);
+        for t in [0.5, 3., 20.] {
+            let projected: Vec2 = camera
+                .project(ray.origin + ray.direction * t)
+                .expect("point behind the camera");
+            assert!(
+                projected.distance(coord) < 1e-2,
+                "{} projects to {} at t = {}",
+                coord,
+                projected,
+                t
+            );
+        }
+    }
+}
+
+/// Marching in packets of four rays gives the same image as marching each
+/// ray on its own.
+#[test]
+fn packets_match_scalar() {
+    let mut scenes: Vec<_> = std::fs::read_dir(root().join("tests/scenes"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "scene"))
+        .collect();
+    scenes.sort();
+    assert!(!scenes.is_empty());
+
+    for path in scenes {
+        let file = SceneFile::load(path.to_str().unwrap()).unwrap_or_else(|e| panic!("{}", e));
+        let mut camera = file
+            .camera
+            .unwrap_or_else(|| Camera::new_with_pos(vec3(0., 1., 6.), vec3(0., 0., -1.)));
+        camera.resolution = vec2(WIDTH as f32, HEIGHT as f32);
+        assert!(
+            RayMarching { scene: &file.scene }.supports_packets(),
+            "{} does not march in packets",
+            path.display()
+        );
+
+        let mut renderer = Renderer::new();
+        let packets = renderer.render_image(&file.scene, &camera, 1);
+        renderer.packets = false;
+        let scalar = renderer.render_image(&file.scene, &camera, 1);
+        assert!(
+            packets == scalar,
+            "{}: packet and scalar renders differ",
+            path.display()
+        );
+    }
+}